@@ -0,0 +1,399 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use parking_lot::Mutex;
+use serde::Serialize;
+use tauri::{AppHandle, Manager, State};
+use tracing::{error, info, warn};
+
+use crate::credentials::{self, api_key_name, webdav_password_name};
+use crate::dal::library::desktop::{Desktop, DESKTOP_USER_ID};
+use crate::dal::library::Library;
+use crate::dal::webdav::clear_clients;
+use crate::dal::zotero::error::ZoteroError;
+use crate::dal::zotero::model::KeyInfo;
+use crate::dal::zotero::{Endpoint, Zotero, LOCAL_USER_ID};
+use crate::error::Error;
+use crate::model::account::{
+    Account, WebDavSettings, KEY_INFO_FILE, LIBRARY_FILE, PINS_FILE, SNAPSHOT_FILE,
+};
+use crate::model::auth::Secret;
+use crate::storage::pin::Pins;
+use crate::storage::{self, DOCUMENT_PATH};
+use crate::stream;
+use crate::transfer::watcher;
+use crate::AppState;
+
+#[derive(Debug, Serialize)]
+pub struct AccountList {
+    pub accounts: Vec<Account>,
+    pub current: Option<i64>,
+}
+
+#[tauri::command(rename_all = "snake_case")]
+pub async fn list_accounts(state: State<'_, Mutex<AppState>>) -> Result<AccountList, Error> {
+    let state = state.lock();
+    Ok(AccountList {
+        accounts: state.accounts.accounts.clone(),
+        current: state.accounts.current,
+    })
+}
+
+#[tauri::command(rename_all = "snake_case")]
+pub async fn switch_account(
+    user_id: i64,
+    state: State<'_, Mutex<AppState>>,
+    app: AppHandle,
+) -> Result<(), Error> {
+    info!("switch to account {}", user_id);
+    let mut state = state.lock();
+    if state.accounts.current == Some(user_id) {
+        return Ok(());
+    }
+    state.transfers.cancel_all();
+    activate(&app, &mut state, user_id)
+}
+
+/// log out of `user_id`, the current account when not given. its credentials and
+/// cached library are deleted, with `remove_files` its downloaded attachments too.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn logout(
+    user_id: Option<i64>,
+    remove_files: bool,
+    state: State<'_, Mutex<AppState>>,
+) -> Result<(), Error> {
+    let mut state = state.lock();
+    let user_id = user_id
+        .or(state.accounts.current)
+        .ok_or(ZoteroError::NotLogin)?;
+    remove_account(&mut state, user_id, remove_files)
+}
+
+/// `storage_root` is only used for new downloads, existing files are not moved.
+///
+/// a new `endpoint` drops the library cached from the old one, the client for it
+/// is built by the next `is_login`.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn update_account(
+    user_id: i64,
+    endpoint: Endpoint,
+    webdav: WebDavSettings,
+    storage_root: Option<PathBuf>,
+    state: State<'_, Mutex<AppState>>,
+    app: AppHandle,
+) -> Result<(), Error> {
+    let mut state = state.lock();
+    let account = state
+        .accounts
+        .get_mut(user_id)
+        .ok_or(ZoteroError::NotLogin)?;
+    account.webdav = webdav;
+    let endpoint_changed = account.endpoint != endpoint;
+    account.endpoint = endpoint;
+    let root_changed = account.storage_root != storage_root;
+    account.storage_root = storage_root.clone();
+    let account = account.clone();
+    state.accounts.save()?;
+    clear_clients();
+
+    if endpoint_changed {
+        forget_library(&mut state, user_id);
+        let dir = state.accounts.dir(user_id);
+        std::fs::remove_file(dir.join(KEY_INFO_FILE)).ok();
+        if state.accounts.current == Some(user_id) {
+            state.zotero = None;
+            stream::stop();
+        }
+    }
+
+    if root_changed && state.accounts.current == Some(user_id) {
+        state.transfers.cancel_all();
+        storage::set_root(storage_root);
+        watch(&app, &account);
+    }
+    Ok(())
+}
+
+/// browse the library of a running zotero 7 desktop through its local api, no api
+/// key is needed. attachments are opened from `storage_dir`, the desktop storage
+/// directory which is `~/Zotero/storage` by default.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn connect_local(
+    endpoint: Option<Endpoint>,
+    storage_dir: Option<PathBuf>,
+    state: State<'_, Mutex<AppState>>,
+    app: AppHandle,
+) -> Result<(), Error> {
+    let endpoint = endpoint.unwrap_or_else(Endpoint::local);
+    Zotero::local(endpoint.clone()).ping().await?;
+    let storage_dir = match storage_dir {
+        Some(x) => x,
+        None => app
+            .path()
+            .home_dir()
+            .context("home directory not found")?
+            .join("Zotero")
+            .join("storage"),
+    };
+    info!("connect to local zotero, storage: {:?}", storage_dir);
+
+    let info = KeyInfo {
+        user_id: LOCAL_USER_ID,
+        user_name: "local".to_string(),
+        access: Default::default(),
+    };
+    let mut state = state.lock();
+    if state
+        .accounts
+        .get(LOCAL_USER_ID)
+        .is_some_and(|x| x.endpoint != endpoint)
+    {
+        forget_library(&mut state, LOCAL_USER_ID);
+    }
+    state.accounts.add(&info, &endpoint);
+    if let Some(account) = state.accounts.get_mut(LOCAL_USER_ID) {
+        account.storage_root = Some(storage_dir);
+    }
+    state.accounts.save()?;
+
+    if state.accounts.current != Some(LOCAL_USER_ID) {
+        state.transfers.cancel_all();
+    }
+    activate(&app, &mut state, LOCAL_USER_ID)
+}
+
+/// read the library from a `zotero.sqlite` copied from zotero desktop, for devices
+/// without network. attachments are opened from `storage_dir`, the `storage`
+/// directory next to the database by default. `refresh` reads the database again.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn connect_database(
+    database: PathBuf,
+    storage_dir: Option<PathBuf>,
+    state: State<'_, Mutex<AppState>>,
+    app: AppHandle,
+) -> Result<(), Error> {
+    let snapshot = state
+        .lock()
+        .accounts
+        .dir(DESKTOP_USER_ID)
+        .join(SNAPSHOT_FILE);
+    let d1 = database.clone();
+    let user_name = tokio::task::spawn_blocking(move || -> Result<_, Error> {
+        let desktop = Desktop::snapshot(&d1, &snapshot)?;
+        // fails early on a file that is not a zotero database
+        desktop.load()?;
+        Ok(desktop.user_name())
+    })
+    .await??;
+    let storage_dir = match storage_dir {
+        Some(x) => x,
+        None => database
+            .parent()
+            .context("database has no parent directory")?
+            .join("storage"),
+    };
+    info!(
+        "read zotero database {:?}, storage: {:?}",
+        database, storage_dir
+    );
+
+    let info = KeyInfo {
+        user_id: DESKTOP_USER_ID,
+        user_name: user_name.unwrap_or_else(|| "local".to_string()),
+        access: Default::default(),
+    };
+    let mut state = state.lock();
+    state.accounts.add(&info, &Endpoint::default());
+    if let Some(account) = state.accounts.get_mut(DESKTOP_USER_ID) {
+        account.storage_root = Some(storage_dir);
+        account.database = Some(database);
+    }
+    state.accounts.save()?;
+
+    if state.accounts.current != Some(DESKTOP_USER_ID) {
+        state.transfers.cancel_all();
+    }
+    activate(&app, &mut state, DESKTOP_USER_ID)
+}
+
+/// check `api_key` against `endpoint`, remember its account and make it the current one
+pub(crate) async fn add_account(
+    app: &AppHandle,
+    endpoint: Endpoint,
+    api_key: Secret,
+) -> Result<Zotero, Error> {
+    let info = Zotero::key_info(&endpoint, &api_key).await?;
+    let zotero = Zotero::from_key_info(endpoint.clone(), api_key.clone(), &info);
+
+    let state = app.state::<Mutex<AppState>>();
+    let mut state = state.lock();
+    state
+        .credentials
+        .set(&api_key_name(info.user_id), &api_key)?;
+    if state
+        .accounts
+        .get(info.user_id)
+        .is_some_and(|x| x.endpoint != endpoint)
+    {
+        forget_library(&mut state, info.user_id);
+    }
+    state.accounts.add(&info, &endpoint);
+    state.accounts.save()?;
+    info.save(&state.accounts.dir(info.user_id).join(KEY_INFO_FILE))?;
+
+    if state.accounts.current != Some(info.user_id) {
+        state.transfers.cancel_all();
+    }
+    activate(app, &mut state, info.user_id)?;
+    state.zotero = Some(zotero.clone());
+    stream::start(app.clone(), &zotero);
+    Ok(zotero)
+}
+
+/// load everything kept for `user_id` into the state. the zotero client is built
+/// later by `is_login`, from the cached key info.
+pub(crate) fn activate(app: &AppHandle, state: &mut AppState, user_id: i64) -> Result<(), Error> {
+    let account = state
+        .accounts
+        .get(user_id)
+        .cloned()
+        .ok_or(ZoteroError::NotLogin)?;
+    state.accounts.current = Some(user_id);
+    state.accounts.save()?;
+
+    let dir = state.accounts.dir(user_id);
+    state.zotero = None;
+    stream::stop();
+    state.api_key = state
+        .credentials
+        .get(&api_key_name(user_id))
+        .inspect_err(|e| error!("read api key failed: {:?}", e))
+        .ok()
+        .flatten();
+    state.data = match super::refresh::load_cached(&dir.join(LIBRARY_FILE)) {
+        Ok(data) => data,
+        Err(e) => {
+            error!("load cached library failed: {:?}", e);
+            None
+        }
+    };
+    state.pins = Pins::load(&dir.join(PINS_FILE));
+    if account.is_local() && account.database.is_none() {
+        state.zotero = Some(Zotero::local(account.endpoint.clone()));
+    }
+
+    storage::set_root(account.storage_root.clone());
+    watch(app, &account);
+    info!("account {} is active", user_id);
+    Ok(())
+}
+
+/// watch the storage root for changes to upload. the files of a local account
+/// belong to zotero desktop, they are never uploaded.
+fn watch(app: &AppHandle, account: &Account) {
+    if account.is_local() {
+        watcher::stop();
+    } else {
+        watcher::start(app.clone());
+    }
+}
+
+pub(crate) fn remove_account(
+    state: &mut AppState,
+    user_id: i64,
+    remove_files: bool,
+) -> Result<(), Error> {
+    info!("log out of account {}", user_id);
+    let Some(account) = state.accounts.get(user_id).cloned() else {
+        return Ok(());
+    };
+    if state.accounts.current == Some(user_id) {
+        state.transfers.cancel_all();
+        state.zotero = None;
+        stream::stop();
+        state.api_key = None;
+        state.data = None;
+        state.pins = Pins::default();
+    }
+
+    state.credentials.remove(&api_key_name(user_id))?;
+    state.credentials.remove(&webdav_password_name(user_id))?;
+    clear_clients();
+
+    let dir = state.accounts.dir(user_id);
+    // the files of a local account belong to zotero desktop
+    if remove_files && !account.is_local() {
+        let root = account
+            .storage_root
+            .unwrap_or_else(|| PathBuf::from(DOCUMENT_PATH));
+        remove_downloads(&dir.join(LIBRARY_FILE), &root)?;
+    }
+    if let Err(e) = std::fs::remove_dir_all(&dir) {
+        warn!("remove {:?} failed: {:?}", dir, e);
+    }
+
+    state.accounts.remove(user_id);
+    state.accounts.save()?;
+    Ok(())
+}
+
+/// the library cached for `user_id` came from another endpoint
+fn forget_library(state: &mut AppState, user_id: i64) {
+    let path = state.accounts.dir(user_id).join(LIBRARY_FILE);
+    if let Err(e) = std::fs::remove_file(&path) {
+        warn!("remove {:?} failed: {:?}", path, e);
+    }
+    if state.accounts.current == Some(user_id) {
+        state.data = None;
+    }
+}
+
+/// the storage root may be shared, so only directories of the library's own
+/// attachments are removed
+fn remove_downloads(library: &Path, root: &Path) -> Result<(), Error> {
+    let (_, items) = Library::open(library)?.load()?;
+    for item in items {
+        let dir = root.join(&item.key);
+        if dir.is_dir() {
+            std::fs::remove_dir_all(&dir)?;
+        }
+    }
+    Ok(())
+}
+
+/// move the single account data of older versions into the account's own directory
+pub(crate) fn migrate_legacy(state: &mut AppState, data_dir: &Path) {
+    if !state.accounts.accounts.is_empty() {
+        return;
+    }
+    let Some(info) = KeyInfo::load(&data_dir.join(KEY_INFO_FILE)) else {
+        return;
+    };
+    info!("migrate data of {} into its account", info.user_id);
+    state.accounts.add(&info, &Endpoint::default());
+    state.accounts.current = Some(info.user_id);
+
+    let dir = state.accounts.dir(info.user_id);
+    for name in [LIBRARY_FILE, KEY_INFO_FILE, PINS_FILE] {
+        let path = data_dir.join(name);
+        if path.exists() {
+            if let Err(e) = std::fs::rename(&path, dir.join(name)) {
+                error!("move {:?} failed: {:?}", path, e);
+            }
+        }
+    }
+    for (from, to) in [
+        (credentials::API_KEY.to_string(), api_key_name(info.user_id)),
+        (
+            credentials::WEBDAV_PASSWORD.to_string(),
+            webdav_password_name(info.user_id),
+        ),
+    ] {
+        if let Err(e) = state.credentials.rename(&from, &to) {
+            error!("migrate {} failed: {:?}", from, e);
+        }
+    }
+    if let Err(e) = state.accounts.save() {
+        error!("save accounts failed: {:?}", e);
+    }
+}
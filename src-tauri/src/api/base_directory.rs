@@ -0,0 +1,34 @@
+use std::path::PathBuf;
+
+use parking_lot::Mutex;
+use tauri::{Manager, State};
+use tracing::info;
+
+use crate::error::Error;
+use crate::AppState;
+
+/// local folder that linked files stored relative to zotero desktop's
+/// "Linked Attachment Base Directory" are resolved against
+#[tauri::command(rename_all = "snake_case")]
+pub async fn get_base_directory(
+    state: State<'_, Mutex<AppState>>,
+) -> Result<Option<PathBuf>, Error> {
+    Ok(state.lock().base_directory.clone())
+}
+
+#[tauri::command(rename_all = "snake_case")]
+pub async fn set_base_directory(
+    path: Option<&str>,
+    state: State<'_, Mutex<AppState>>,
+    app: tauri::AppHandle,
+) -> Result<(), Error> {
+    info!("set base directory: {:?}", path);
+    let file = app.path().app_data_dir().unwrap().join("base_directory");
+    match path {
+        Some(path) => std::fs::write(file, path.as_bytes())?,
+        None if file.exists() => std::fs::remove_file(file)?,
+        None => {}
+    }
+    state.lock().base_directory = path.map(PathBuf::from);
+    Ok(())
+}
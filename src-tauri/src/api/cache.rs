@@ -0,0 +1,81 @@
+use ahash::AHashMap;
+use parking_lot::Mutex;
+use serde::Serialize;
+use tauri::State;
+
+use crate::dal::zotero::error::ZoteroError;
+use crate::error::Error;
+use crate::model::zotero_data::EMPTY_COLLECTION_KEY;
+use crate::storage::cache;
+use crate::AppState;
+
+#[derive(Debug, Serialize)]
+pub struct CollectionUsage {
+    /// empty for items which are not in any collection
+    pub collection_key: String,
+    pub name: String,
+    /// downloaded attachments of the collection's own items
+    pub files: usize,
+    /// bytes
+    pub size: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CacheUsage {
+    /// bytes, all downloaded attachments
+    pub total_size: u64,
+    /// bytes, 0 means no limit
+    pub quota: u64,
+    pub collections: Vec<CollectionUsage>,
+}
+
+#[tauri::command(rename_all = "snake_case")]
+pub async fn get_cache_usage(state: State<'_, Mutex<AppState>>) -> Result<CacheUsage, Error> {
+    let entries = tokio::task::spawn_blocking(cache::entries).await?;
+    let sizes: AHashMap<&str, u64> = entries.iter().map(|x| (x.key.as_str(), x.size)).collect();
+
+    let state = state.lock();
+    let data = state.data.as_ref().ok_or(ZoteroError::NoData)?;
+
+    let mut collections: Vec<CollectionUsage> = data
+        .collections_item_map
+        .keys()
+        .map(|collection_key| {
+            let mut usage = CollectionUsage {
+                collection_key: collection_key.clone(),
+                name: data
+                    .find_collection(collection_key)
+                    .map(|x| x.name.clone())
+                    .unwrap_or_default(),
+                files: 0,
+                size: 0,
+            };
+            let keys = data.collection_keys(collection_key, false);
+            for item in data
+                .collection_items(&keys)
+                .into_iter()
+                .flat_map(|x| x.attachments())
+            {
+                if let Some(size) = sizes.get(item.key.as_str()) {
+                    usage.files += 1;
+                    usage.size += size;
+                }
+            }
+            usage
+        })
+        .filter(|x| x.files > 0 || x.collection_key == EMPTY_COLLECTION_KEY)
+        .collect();
+    collections.sort_by_key(|x| std::cmp::Reverse(x.size));
+
+    Ok(CacheUsage {
+        total_size: entries.iter().map(|x| x.size).sum(),
+        quota: state.settings.cache_quota,
+        collections,
+    })
+}
+
+/// evict attachments until the cache fits into the quota, returns the freed bytes
+#[tauri::command(rename_all = "snake_case")]
+pub async fn trim_cache(app: tauri::AppHandle) -> Result<u64, Error> {
+    Ok(cache::trim(&app, None).await?)
+}
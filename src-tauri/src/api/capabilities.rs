@@ -0,0 +1,13 @@
+use parking_lot::Mutex;
+use tauri::State;
+
+use crate::dal::zotero::error::ZoteroError;
+use crate::dal::zotero::model::Capabilities;
+use crate::error::Error;
+use crate::AppState;
+
+/// what the api key of the current account allows, and why features are disabled
+#[tauri::command(rename_all = "snake_case")]
+pub async fn get_capabilities(state: State<'_, Mutex<AppState>>) -> Result<Capabilities, Error> {
+    Ok(state.lock().capabilities().ok_or(ZoteroError::NotLogin)?)
+}
@@ -0,0 +1,46 @@
+use parking_lot::Mutex;
+use tauri::State;
+use tracing::info;
+
+use crate::credentials::{api_key_name, webdav_password_name};
+use crate::dal::webdav::clear_clients;
+use crate::dal::zotero::error::ZoteroError;
+use crate::error::Error;
+use crate::AppState;
+
+/// true when the secrets can not be read until a passphrase is given
+#[tauri::command(rename_all = "snake_case")]
+pub async fn is_credentials_locked(state: State<'_, Mutex<AppState>>) -> Result<bool, Error> {
+    Ok(state.lock().credentials.is_locked())
+}
+
+#[tauri::command(rename_all = "snake_case")]
+pub async fn unlock_credentials(
+    passphrase: &str,
+    state: State<'_, Mutex<AppState>>,
+) -> Result<(), Error> {
+    let mut state = state.lock();
+    state.credentials.unlock(passphrase)?;
+    info!("credentials unlocked");
+    if let Some(user_id) = state.accounts.current {
+        state.api_key = state.credentials.get(&api_key_name(user_id))?;
+    }
+    Ok(())
+}
+
+/// password of the current account, `None` removes it and leaves webdav not configured
+#[tauri::command(rename_all = "snake_case")]
+pub async fn set_webdav_password(
+    password: Option<&str>,
+    state: State<'_, Mutex<AppState>>,
+) -> Result<(), Error> {
+    let mut state = state.lock();
+    let user_id = state.accounts.current.ok_or(ZoteroError::NotLogin)?;
+    let name = webdav_password_name(user_id);
+    match password {
+        Some(password) => state.credentials.set(&name, &password.into())?,
+        None => state.credentials.remove(&name)?,
+    }
+    clear_clients();
+    Ok(())
+}
@@ -1,212 +1,182 @@
-use std::io::{self, Write};
-use std::path::PathBuf;
+use std::path::Path;
 
 use anyhow::Context;
-use dotenvy_macro::dotenv;
-use futures_util::StreamExt;
 use log::info;
-use md5::{Digest, Md5};
 use parking_lot::Mutex;
-use serde::Deserialize;
-use tauri::ipc::Channel;
 use tauri::State;
 use tauri_plugin_shell::ShellExt;
-use tokio::fs;
-use tokio::io::AsyncReadExt;
 use tracing::debug;
 
-use crate::dal::webdav::{client, WebDavAuth};
+use crate::dal::zotero::api::item::model::LinkMode;
 use crate::dal::zotero::error::ZoteroError;
+use crate::dal::zotero::model::{Capabilities, Feature};
 use crate::error::Error;
+use crate::storage;
+use crate::storage::sync_state::SyncState;
+use crate::transfer::webdav::{get_prop, local_prop, webdav_client};
+use crate::transfer::{sync_direction, Transfer, TransferKind};
 use crate::AppState;
 
-const DOCUMENT_PATH: &str = "/storage/emulated/0/Download/zotero";
-const DATA_PATH: &str = "/storage/emulated/0/Download/";
-
+/// `key` is the parent item key. when `attachment_key` is not given the first pdf
+/// attachment of the item is used.
+///
+/// the transfer runs in the [`TransferManager`](crate::transfer::TransferManager),
+/// progress is reported through its events.
 #[tauri::command(rename_all = "snake_case")]
 pub async fn download_pdf(
     key: &str,
+    attachment_key: Option<&str>,
     state: State<'_, Mutex<AppState>>,
     app: tauri::AppHandle,
-    downloaded_size: Channel<usize>,
 ) -> Result<(), Error> {
-    let client = client(
-        dotenv!("WEB_DAV_AUTH_HOST"),
-        Some(WebDavAuth {
-            username: dotenv!("WEB_DAV_USERNAME").into(),
-            password: dotenv!("WEB_DAV_PASSWORD").into(),
-        }),
-    )?;
-
-    let data = state
-        .lock()
-        .data
-        .as_ref()
-        .map(|x| x.items.clone())
-        .ok_or(ZoteroError::NoData)?;
+    let (data, base_directory, transfers, capabilities, is_local) = {
+        let state = state.lock();
+        let data = state
+            .data
+            .as_ref()
+            .map(|x| x.items.clone())
+            .ok_or(ZoteroError::NoData)?;
+        (
+            data,
+            state.base_directory.clone(),
+            state.transfers.clone(),
+            state.capabilities(),
+            state.accounts.current().is_some_and(|x| x.is_local()),
+        )
+    };
+    let check = |feature| {
+        capabilities
+            .as_ref()
+            .map_or(Ok(()), |x: &Capabilities| x.check(feature))
+    };
 
     let item = data.get(key).ok_or(ZoteroError::NoData)?;
-    let item = item
-        .sub_items
-        .iter()
-        .find(|x| x.data.content_type == "application/pdf" && x.data.filename.is_some())
-        .ok_or(ZoteroError::NoPdf)?;
-    let key = item.key.as_ref();
+    let item = match attachment_key {
+        Some(attachment_key) => item
+            .attachments()
+            .find(|x| x.key == attachment_key)
+            .ok_or_else(|| ZoteroError::NoAttachment(attachment_key.to_string()))?,
+        None => item
+            .attachments()
+            .find(|x| x.data.content_type == "application/pdf")
+            .ok_or(ZoteroError::NoPdf)?,
+    };
+    let key: &str = item.key.as_ref();
+
+    let direct_path = match item.data.link_mode {
+        Some(LinkMode::LinkedUrl) => {
+            let url = item
+                .data
+                .url
+                .as_ref()
+                .ok_or_else(|| ZoteroError::NoAttachment(key.to_string()))?;
+            info!("open linked url: {}", url);
+            app.shell().open(url, None)?;
+            return Ok(());
+        }
+        Some(LinkMode::LinkedFile) => {
+            Some(storage::linked_file_path(item, base_directory.as_deref())?)
+        }
+        // zotero desktop keeps the file in its storage directory, there is nothing to sync
+        _ if is_local => storage::attachment_file_path(item),
+        _ => None,
+    };
+    if let Some(path) = direct_path {
+        if !path.exists() {
+            return Err(ZoteroError::LinkedFileMissing(path.display().to_string()).into());
+        }
+        info!("open file: {:?}", path);
+        open_file(&app, &path)?;
+        return Ok(());
+    }
 
-    let prop = client
-        .get("/zotero/".to_string() + key + ".prop")
-        .await?
-        .text()
-        .await?;
-    let prop: Properties = quick_xml::de::from_str(&prop).context("parse prop failed")?;
-    let data_path = DATA_PATH
-        .parse::<PathBuf>()
-        .unwrap()
-        .join(item.data.filename.as_ref().unwrap());
+    storage::migrate_legacy_file(item)?;
+    let data_path = storage::attachment_file_path(item)
+        .ok_or_else(|| ZoteroError::NoAttachment(key.to_string()))?;
+    let filename = item.data.filename.clone().unwrap_or_default();
 
     debug!("data path: {:?}", data_path);
 
-    if data_path.exists() {
-        let mut file = tokio::fs::File::open(&data_path)
-            .await
-            .context("open file failed")?;
-        let meta = file.metadata().await?;
-        let file_modify_time =
-            chrono::DateTime::<chrono::Utc>::from(meta.modified()?).timestamp_millis();
-        let mut h = Md5::new();
-        let mut data = Vec::with_capacity(meta.len() as usize);
-        file.read_to_end(&mut data).await?;
-        h.update(&data);
-        let hash = hex::encode(h.finalize());
+    if !data_path.exists() {
+        check(Feature::Download)?;
+    }
 
-        debug!("old hash: {}, new hash: {}", prop.hash, hash);
+    let kind = if data_path.exists() {
+        let synced = SyncState::load(key);
+        let client = webdav_client(&state.lock());
+        let prop = match client {
+            Ok(client) => get_prop(&client, key).await,
+            Err(e) => Err(e),
+        };
+        let prop = match prop {
+            Ok(prop) => prop,
+            // offline or without webdav, a file that matched the server when it was
+            // synced is opened as it is
+            Err(e) if synced.as_ref().is_some_and(|x| x.verified) => {
+                info!("open {} without checking the server: {}", key, e);
+                storage::cache::touch(key);
+                open_file(&app, &data_path)?;
+                return Ok(());
+            }
+            Err(e) => return Err(e),
+        };
+        let local = local_prop(&data_path).await?;
+
+        debug!("old hash: {}, new hash: {}", prop.hash, local.hash);
         debug!(
             "file modify time: {}, new file modify time: {}",
             chrono::DateTime::<chrono::Utc>::from_timestamp_millis(prop.mtime)
                 .unwrap()
                 .with_timezone(&chrono::Local),
-            chrono::DateTime::<chrono::Utc>::from_timestamp_millis(file_modify_time)
+            chrono::DateTime::<chrono::Utc>::from_timestamp_millis(local.mtime)
                 .unwrap()
                 .with_timezone(&chrono::Local)
         );
 
-        if hash == prop.hash {
-            tracing::info!("file already exists: {:?}", data_path);
-            app.shell().open(data_path.to_str().unwrap(), None)?;
-            return Ok(());
-        } else if prop.mtime > file_modify_time {
-            tracing::info!("file is outdated: {:?}", data_path);
-        } else {
-            info!("start upload");
-            let new_prop = get_new_prop(Properties {
-                mtime: file_modify_time,
-                hash,
-            });
-
-            let mut zip_data = Vec::new();
-            {
-                let mut zip = zip::ZipWriter::new(std::io::Cursor::new(&mut zip_data));
-                let options = zip::write::SimpleFileOptions::default();
-
-                zip.start_file(format!("{}.pdf", key), options)?;
-                zip.write_all(&data)?;
-                zip.finish()?;
+        let direction = match sync_direction(&local, &prop, synced.as_ref()) {
+            Some(TransferKind::Upload) => match check(Feature::Upload) {
+                Ok(()) => Some(TransferKind::Upload),
+                Err(e) => {
+                    info!("open local changes without upload: {}", e);
+                    None
+                }
+            },
+            x => x,
+        };
+        match direction {
+            None => {
+                tracing::info!("file already exists: {:?}", data_path);
+                storage::cache::touch(key);
+                open_file(&app, &data_path)?;
+                return Ok(());
             }
-
-            client.put(format!("/zotero/{}.zip", key), zip_data).await?;
-
-            client
-                .put("/zotero/".to_string() + key + ".prop", new_prop)
-                .await?;
-
-            app.shell().open(data_path.to_str().unwrap(), None)?;
-            return Ok(());
-        }
-    }
-
-    let resp = client.get("/zotero/".to_string() + key + ".zip").await?;
-    let size = resp.content_length().unwrap_or(0);
-
-    let path = DOCUMENT_PATH.parse::<PathBuf>().unwrap().join(key);
-
-    fs::create_dir_all(&path)
-        .await
-        .context("create dir failed")?;
-
-    let mut stream = resp.bytes_stream();
-    let mut data = Vec::with_capacity(size as usize);
-    while let Some(chunk) = stream.next().await {
-        match chunk {
-            Ok(bytes) => {
-                data.extend(bytes);
-                downloaded_size.send(data.len()).ok();
+            Some(TransferKind::Download) => {
+                tracing::info!("file is outdated: {:?}", data_path);
+                check(Feature::Download)?;
+                TransferKind::Download
             }
-            Err(e) => return Err(e.into()),
-        };
-    }
-
-    let pdf_path = tokio::task::spawn_blocking(move || -> Result<Option<PathBuf>, Error> {
-        let reader = std::io::BufReader::new(std::io::Cursor::new(data));
-        let mut archive = zip::ZipArchive::new(reader)?;
-        let mut pdf_path = None;
-
-        for i in 0..archive.len() {
-            let mut file = archive.by_index(i).unwrap();
-            let mut out_path = match file.enclosed_name() {
-                Some(path) => path,
-                None => continue,
-            };
-
-            out_path = if out_path.extension() == Some("pdf".as_ref()) {
-                "/storage/emulated/0/Download/"
-                    .parse::<PathBuf>()
-                    .unwrap()
-                    .join(out_path)
-            } else {
-                path.join(out_path)
-            };
-
-            if file.is_dir() {
-                std::fs::create_dir_all(&out_path)?;
-            } else {
-                if let Some(p) = out_path.parent() {
-                    if !p.exists() {
-                        std::fs::create_dir_all(p)?;
-                    }
-                }
-
-                if out_path.extension() == Some("pdf".as_ref()) {
-                    pdf_path = Some(out_path.clone());
-                }
-
-                let mut out_file = std::fs::File::create(&out_path).with_context(|| {
-                    format!("create unzip file failed: {:?}", out_path.display())
-                })?;
-
-                io::copy(&mut file, &mut out_file)?;
+            Some(TransferKind::Upload) => {
+                info!("start upload");
+                TransferKind::Upload
             }
         }
-        Ok(pdf_path)
-    })
-    .await??;
+    } else {
+        TransferKind::Download
+    };
 
-    if let Some(pdf_path) = pdf_path {
-        tracing::info!("file_path: {:?}", pdf_path);
-        app.shell().open(pdf_path.to_str().unwrap(), None)?;
-    }
+    transfers.enqueue(Transfer::new(key, kind, filename).with_md5(item.data.md5.clone()));
+    transfers.wait(key).await?;
 
+    storage::cache::touch(key);
+    open_file(&app, &data_path)?;
     Ok(())
 }
 
-#[derive(Debug, Deserialize)]
-struct Properties {
-    mtime: i64,
-    hash: String,
-}
-
-fn get_new_prop(prop: Properties) -> String {
-    format!(
-        "<properties version=\"1\"><mtime>{}</mtime><hash>{}</hash></properties>",
-        prop.mtime, prop.hash
-    )
+/// open `path` with the default app of the system
+fn open_file(app: &tauri::AppHandle, path: &Path) -> Result<(), Error> {
+    let path = path
+        .to_str()
+        .with_context(|| format!("{:?} is not a valid utf-8 path", path))?;
+    app.shell().open(path, None)?;
+    Ok(())
 }
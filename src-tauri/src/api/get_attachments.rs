@@ -0,0 +1,46 @@
+use std::path::Path;
+
+use parking_lot::Mutex;
+use tauri::State;
+
+use crate::dal::zotero::api::item::model::Item;
+use crate::dal::zotero::error::ZoteroError;
+use crate::error::Error;
+use crate::model::zotero_data::AttachmentData;
+use crate::storage;
+use crate::AppState;
+
+#[tauri::command(rename_all = "snake_case")]
+pub async fn get_attachments(
+    key: &str,
+    state: State<'_, Mutex<AppState>>,
+) -> Result<Vec<AttachmentData>, Error> {
+    let (items, base_directory) = {
+        let state = state.lock();
+        let items = state
+            .data
+            .as_ref()
+            .map(|x| x.items.clone())
+            .ok_or(ZoteroError::NoData)?;
+        (items, state.base_directory.clone())
+    };
+
+    let item = items.get(key).ok_or(ZoteroError::NoData)?;
+
+    Ok(item
+        .attachments()
+        .map(|x| attachment_data(x, base_directory.as_deref()))
+        .collect())
+}
+
+pub(crate) fn attachment_data(item: &Item, base_directory: Option<&Path>) -> AttachmentData {
+    AttachmentData {
+        key: item.key.clone(),
+        title: item.data.title.clone().unwrap_or_default(),
+        content_type: item.data.content_type.clone(),
+        filename: item.data.filename.clone(),
+        link_mode: item.data.link_mode,
+        remote_size: item.links.enclosure.as_ref().and_then(|x| x.length),
+        local_state: storage::local_state(item, base_directory),
+    }
+}
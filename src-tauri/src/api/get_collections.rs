@@ -1,19 +1,19 @@
-use std::sync::Arc;
-
 use crate::dal::zotero::error::ZoteroError;
 use crate::error::Error;
-use crate::model::zotero_data::{CollectionsData, EMPTY_COLLECTION_KEY};
+use crate::model::zotero_data::{CollectionNode, CollectionsData, EMPTY_COLLECTION_KEY};
 use crate::AppState;
 use parking_lot::Mutex;
 use tauri::State;
 
+/// the collections as a flat list, parents first, with the number of all items and
+/// of those in no collection
 #[tauri::command(rename_all = "snake_case")]
 pub async fn get_collections(
     state: State<'_, Mutex<AppState>>,
-) -> Result<(Arc<Vec<CollectionsData>>, usize, usize), Error> {
+) -> Result<(Vec<CollectionNode>, usize, usize), Error> {
     if let Some(data) = &state.lock().data {
         Ok((
-            data.collections.clone(),
+            CollectionsData::flatten(&data.collections),
             data.items.len(),
             data.collections_item_map
                 .get(EMPTY_COLLECTION_KEY)
@@ -0,0 +1,319 @@
+use std::sync::{Arc, LazyLock};
+use std::time::{Duration, Instant};
+
+use ahash::AHashMap;
+use parking_lot::{Mutex, RwLock};
+use serde_json::Value;
+use tauri::State;
+use tracing::warn;
+
+use crate::api::get_attachments::attachment_data;
+use crate::dal::zotero::api::item::model::Item;
+use crate::dal::zotero::api::schema::model::{CreatorTypeLabel, FieldLabel, ItemTypeLabel};
+use crate::dal::zotero::api::schema::{fallback_label, FIELD_ORDER};
+use crate::dal::zotero::error::ZoteroError;
+use crate::dal::zotero::Zotero;
+use crate::error::Error;
+use crate::model::zotero_data::{
+    CreatorData, FieldData, ItemDetail, NoteData, SimpleItemData, TagData,
+};
+use crate::storage::fulltext;
+use crate::AppState;
+
+const DEFAULT_LOCALE: &str = "en-US";
+/// characters of a note's text used as its title when it has none
+const NOTE_TITLE_LENGTH: usize = 80;
+/// labels are not fetched again this long after a failure, english is used meanwhile
+const LABELS_RETRY: Duration = Duration::from_secs(10 * 60);
+
+/// keys of the item data which are not fields shown to the user
+const NOT_FIELDS: &[&str] = &[
+    "key",
+    "version",
+    "parentItem",
+    "itemType",
+    "creators",
+    "tags",
+    "collections",
+    "relations",
+    "dateAdded",
+    "dateModified",
+    "contentType",
+    "charset",
+    "filename",
+    "linkMode",
+    "md5",
+    "mtime",
+    "path",
+    "note",
+    "deleted",
+    "inPublications",
+];
+
+/// labels of an item type in one locale
+#[derive(Debug, Default)]
+struct Labels {
+    item_type: Option<String>,
+    /// in display order
+    fields: Vec<FieldLabel>,
+    creator_types: Vec<CreatorTypeLabel>,
+}
+
+/// item type and locale
+type LabelsId = (String, String);
+
+/// labels fetched so far. they only change with zotero's schema, so they are
+/// kept until the app quits.
+static LABELS: LazyLock<RwLock<AHashMap<LabelsId, Arc<Labels>>>> =
+    LazyLock::new(|| RwLock::new(AHashMap::new()));
+
+/// labels of every item type by locale
+static ITEM_TYPES: LazyLock<RwLock<AHashMap<String, Arc<Vec<ItemTypeLabel>>>>> =
+    LazyLock::new(|| RwLock::new(AHashMap::new()));
+
+/// when fetching labels in a locale last failed
+static FAILED: LazyLock<RwLock<AHashMap<String, Instant>>> =
+    LazyLock::new(|| RwLock::new(AHashMap::new()));
+
+/// all of an item with its children, collections and related items. field and
+/// creator type labels are in `locale`, english without the network.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn get_item_detail(
+    key: &str,
+    locale: Option<String>,
+    state: State<'_, Mutex<AppState>>,
+) -> Result<ItemDetail, Error> {
+    let (items, collections, zotero, base_directory) = {
+        let state = state.lock();
+        let data = state.data.as_ref().ok_or(ZoteroError::NoData)?;
+        let item = data.items.get(key).ok_or(ZoteroError::NoData)?;
+        let collections: Vec<_> = item
+            .data
+            .collections
+            .iter()
+            .flatten()
+            .filter_map(|x| data.collection_path(x))
+            .collect();
+        (
+            data.items.clone(),
+            collections,
+            state.zotero.clone(),
+            state.base_directory.clone(),
+        )
+    };
+    let item = items.get(key).ok_or(ZoteroError::NoData)?;
+    let locale = locale.unwrap_or_else(|| DEFAULT_LOCALE.to_string());
+    let labels = labels(zotero.as_ref(), &item.data.item_type, &locale).await;
+
+    let creators = item
+        .data
+        .creators
+        .iter()
+        .map(|x| CreatorData {
+            role: labels
+                .creator_types
+                .iter()
+                .find(|y| y.creator_type == x.creator_type)
+                .map(|y| y.localized.clone())
+                .unwrap_or_else(|| fallback_label(&x.creator_type)),
+            creator_type: x.creator_type.clone(),
+            first_name: x.first_name.clone(),
+            last_name: x.last_name.clone(),
+        })
+        .collect();
+    let tags = item
+        .data
+        .tags
+        .iter()
+        .map(|x| TagData {
+            tag: x.tag.clone(),
+            automatic: x.tag_type == Some(1),
+        })
+        .collect();
+    let related = item
+        .related_keys()
+        .filter_map(|x| items.get(x))
+        .map(|x| SimpleItemData {
+            key: x.key.clone(),
+            title: x.data.title.clone().unwrap_or_default(),
+        })
+        .collect();
+
+    Ok(ItemDetail {
+        key: item.key.clone(),
+        item_type_label: labels
+            .item_type
+            .clone()
+            .unwrap_or_else(|| fallback_label(&item.data.item_type)),
+        item_type: item.data.item_type.clone(),
+        title: item.data.title.clone().unwrap_or_default(),
+        fields: fields(item, &labels),
+        creators,
+        tags,
+        collections,
+        attachments: item
+            .attachments()
+            .map(|x| attachment_data(x, base_directory.as_deref()))
+            .collect(),
+        notes: item.notes().map(note_data).collect(),
+        related,
+        date_added: item.data.date_added.clone(),
+        date_modified: item.data.date_modified.clone(),
+    })
+}
+
+/// from the api when possible, fetched labels are cached. the local api of
+/// zotero desktop has no schema, it always gets the fallback.
+async fn labels(zotero: Option<&Zotero>, item_type: &str, locale: &str) -> Arc<Labels> {
+    let id = (item_type.to_string(), locale.to_string());
+    if let Some(labels) = LABELS.read().get(&id) {
+        return labels.clone();
+    }
+    let Some(zotero) = zotero.filter(|x| !x.is_local()) else {
+        return Arc::default();
+    };
+    if FAILED
+        .read()
+        .get(locale)
+        .is_some_and(|x| x.elapsed() < LABELS_RETRY)
+    {
+        return Arc::default();
+    }
+    let result = tokio::try_join!(
+        item_types(zotero, locale),
+        zotero.item_type_fields(item_type, locale),
+        zotero.item_type_creator_types(item_type, locale),
+    );
+    match result {
+        Ok((item_types, fields, creator_types)) => {
+            let labels = Arc::new(Labels {
+                item_type: item_types
+                    .iter()
+                    .find(|x| x.item_type == item_type)
+                    .map(|x| x.localized.clone()),
+                fields,
+                creator_types,
+            });
+            LABELS.write().insert(id, labels.clone());
+            labels
+        }
+        Err(e) => {
+            warn!("get labels of {} failed: {:?}", item_type, e);
+            FAILED.write().insert(locale.to_string(), Instant::now());
+            Arc::default()
+        }
+    }
+}
+
+/// labels of every item type, fetched once per locale
+async fn item_types(zotero: &Zotero, locale: &str) -> Result<Arc<Vec<ItemTypeLabel>>, ZoteroError> {
+    if let Some(item_types) = ITEM_TYPES.read().get(locale) {
+        return Ok(item_types.clone());
+    }
+    let item_types = Arc::new(zotero.item_types(locale).await?);
+    ITEM_TYPES
+        .write()
+        .insert(locale.to_string(), item_types.clone());
+    Ok(item_types)
+}
+
+/// non-empty fields, in the schema's order when it is known
+fn fields(item: &Item, labels: &Labels) -> Vec<FieldData> {
+    let Ok(Value::Object(data)) = serde_json::to_value(&item.data) else {
+        return vec![];
+    };
+    let position = |field: &str| {
+        if labels.fields.is_empty() {
+            FIELD_ORDER.iter().position(|x| *x == field)
+        } else {
+            labels.fields.iter().position(|x| x.field == field)
+        }
+        .unwrap_or(usize::MAX)
+    };
+    let mut fields: Vec<FieldData> = data
+        .into_iter()
+        .filter(|(k, _)| !NOT_FIELDS.contains(&k.as_str()))
+        .filter_map(|(field, value)| {
+            let value = match value {
+                Value::String(x) if !x.trim().is_empty() => x,
+                Value::Number(x) => x.to_string(),
+                _ => return None,
+            };
+            let label = labels
+                .fields
+                .iter()
+                .find(|x| x.field == field)
+                .map(|x| x.localized.clone())
+                .unwrap_or_else(|| fallback_label(&field));
+            Some(FieldData {
+                field,
+                label,
+                value,
+            })
+        })
+        .collect();
+    fields.sort_by(|a, b| {
+        position(&a.field)
+            .cmp(&position(&b.field))
+            .then_with(|| a.field.cmp(&b.field))
+    });
+    fields
+}
+
+fn note_data(note: &Item) -> NoteData {
+    let html = note.note().unwrap_or_default();
+    let title = match note.data.title.as_deref() {
+        Some(title) if !title.is_empty() => title.to_string(),
+        _ => fulltext::html_text(html)
+            .chars()
+            .take(NOTE_TITLE_LENGTH)
+            .collect(),
+    };
+    NoteData {
+        key: note.key.clone(),
+        title,
+        note: html.to_string(),
+        date_modified: note.data.date_modified.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fields() {
+        let item: Item = serde_json::from_str(
+            r#"{"key": "ITEM", "data": {
+                "itemType": "journalArticle", "title": "deep nets", "volume": "3",
+                "publicationTitle": "journal", "extra": "", "url": "https://a.b",
+                "dateAdded": "2024-01-02T03:04:05Z"
+            }}"#,
+        )
+        .unwrap();
+
+        let names = |fields: Vec<FieldData>| -> Vec<String> {
+            fields.into_iter().map(|x| x.field).collect()
+        };
+        assert_eq!(
+            names(fields(&item, &Labels::default())),
+            ["title", "publicationTitle", "volume", "url"]
+        );
+
+        let labels = Labels {
+            fields: ["url", "title"]
+                .iter()
+                .map(|x| FieldLabel {
+                    field: x.to_string(),
+                    localized: x.to_uppercase(),
+                })
+                .collect(),
+            ..Default::default()
+        };
+        let fields = fields(&item, &labels);
+        assert_eq!(fields[0].label, "URL");
+        assert_eq!(fields[1].label, "TITLE");
+        // fields the schema does not know come last
+        assert_eq!(fields[2].label, "Publication title");
+    }
+}
@@ -1,36 +1,84 @@
-use std::sync::Arc;
+use std::cmp::Ordering;
+use std::path::Path;
 
 use parking_lot::Mutex;
 use tauri::State;
 
+use crate::dal::zotero::api::item::model::Item;
 use crate::dal::zotero::error::ZoteroError;
 use crate::error::Error;
-use crate::model::zotero_data::SimpleItemData;
+use crate::model::zotero_data::{ItemPage, ItemRow, SortEntry, SortKey, SortOrder};
+use crate::storage;
 use crate::AppState;
 
+const DEFAULT_LIMIT: usize = 50;
+const MAX_LIMIT: usize = 500;
+
+/// one page of the top level items of `collection_key`, `all-items` for the whole
+/// library. `recursive` adds the items of all subcollections, each only once. the
+/// next page starts after `cursor`, the `next_cursor` of the previous page, so
+/// items added or removed by a refresh in between do not shift it.
 #[tauri::command(rename_all = "snake_case")]
 pub async fn get_items_by_collection(
     collection_key: &str,
+    recursive: Option<bool>,
+    sort: Option<SortKey>,
+    order: Option<SortOrder>,
+    cursor: Option<String>,
+    limit: Option<usize>,
     state: State<'_, Mutex<AppState>>,
-) -> Result<Arc<Vec<SimpleItemData>>, Error> {
-    if let Some(data) = &state.lock().data {
-        if collection_key == "all-items" {
-            return Ok(Arc::new(
-                data.items
-                    .values()
-                    .map(|x| SimpleItemData {
-                        key: x.key.clone(),
-                        title: x.data.title.clone().unwrap_or_default(),
-                    })
-                    .collect(),
-            ));
+) -> Result<ItemPage, Error> {
+    let order = order.unwrap_or_default();
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let cursor: Option<SortEntry> = cursor
+        .map(|x| serde_json::from_str(&x))
+        .transpose()
+        .map_err(ZoteroError::Data)?;
+
+    let state = state.lock();
+    let data = state.data.as_ref().ok_or(ZoteroError::NoData)?;
+    let listing = data.listing(
+        collection_key,
+        recursive.unwrap_or_default(),
+        sort.unwrap_or_default(),
+        order,
+    );
+    let start = match &cursor {
+        Some(cursor) => listing.partition_point(|x| x.cmp(cursor, order) != Ordering::Greater),
+        None => 0,
+    };
+    let page = &listing[start..(start + limit).min(listing.len())];
+    let next_cursor = match page.last() {
+        Some(last) if start + page.len() < listing.len() => {
+            Some(serde_json::to_string(last).map_err(ZoteroError::Data)?)
         }
-        Ok(data
-            .collections_item_map
-            .get(collection_key)
-            .cloned()
-            .unwrap_or_default())
-    } else {
-        Err(Error::Zotero(ZoteroError::NoData))
+        _ => None,
+    };
+    let rows = page
+        .iter()
+        .filter_map(|x| data.items.get(&x.key))
+        .map(|x| row(x, state.base_directory.as_deref()))
+        .collect();
+    Ok(ItemPage {
+        rows,
+        total: listing.len(),
+        next_cursor,
+    })
+}
+
+fn row(item: &Item, base_directory: Option<&Path>) -> ItemRow {
+    let file = item
+        .attachments()
+        .find(|x| x.data.content_type == "application/pdf")
+        .or_else(|| item.attachments().next());
+    ItemRow {
+        key: item.key.clone(),
+        title: item.data.title.clone().unwrap_or_default(),
+        creator_summary: item.creator_summary(),
+        year: item.year(),
+        item_type: item.data.item_type.clone(),
+        attachments: item.attachments().count(),
+        notes: item.notes().count(),
+        local_state: file.map(|x| storage::local_state(x, base_directory)),
     }
 }
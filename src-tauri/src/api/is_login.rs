@@ -1,19 +1,124 @@
-use crate::dal::zotero::Zotero;
+use crate::api::account::{add_account, remove_account};
+use crate::credentials::{self, webdav_password_name};
+use crate::dal::zotero::model::KeyInfo;
+use crate::dal::zotero::{Endpoint, Zotero};
 use crate::error::Error;
+use crate::model::account::KEY_INFO_FILE;
+use crate::model::auth::Secret;
+use crate::stream;
 use crate::AppState;
 use parking_lot::Mutex;
-use tauri::State;
+use tauri::{Emitter, Manager, State};
+use tracing::{error, info, warn};
 
+/// emitted when the stored api key turned out to be revoked
+pub const LOGOUT_EVENT: &str = "logout";
+
+/// with a cached [`KeyInfo`] this does not need the network, the key is checked
+/// again in the background
 #[tauri::command(rename_all = "snake_case")]
-pub async fn is_login(state: State<'_, Mutex<AppState>>) -> Result<bool, Error> {
-    let api_key = state.lock().api_key.clone();
-    if let Some(api_key) = api_key {
-        if state.lock().zotero.is_none() {
-            let zotero = Zotero::new(api_key).await?;
-            state.lock().zotero = Some(zotero);
+pub async fn is_login(
+    state: State<'_, Mutex<AppState>>,
+    app: tauri::AppHandle,
+) -> Result<bool, Error> {
+    let (api_key, account, dir) = {
+        let state = state.lock();
+        (
+            state.api_key.clone(),
+            state.accounts.current().cloned(),
+            state.accounts.current_dir(),
+        )
+    };
+    // the client of a local account is built when it is activated
+    if account.as_ref().is_some_and(|x| x.is_local()) {
+        return Ok(true);
+    }
+    let (Some(api_key), Some(account), Some(dir)) = (api_key, account, dir) else {
+        return migrate_legacy_key(&app).await;
+    };
+    if state.lock().zotero.is_none() {
+        let endpoint = account.endpoint;
+        let path = dir.join(KEY_INFO_FILE);
+        match KeyInfo::load(&path) {
+            Some(info) => {
+                let zotero = Zotero::from_key_info(endpoint.clone(), api_key.clone(), &info);
+                stream::start(app.clone(), &zotero);
+                state.lock().zotero = Some(zotero);
+                tauri::async_runtime::spawn(revalidate(app, endpoint, api_key));
+            }
+            None => {
+                let info = Zotero::key_info(&endpoint, &api_key).await?;
+                info.save(&path)?;
+                let zotero = Zotero::from_key_info(endpoint, api_key, &info);
+                stream::start(app, &zotero);
+                state.lock().zotero = Some(zotero);
+            }
+        }
+    }
+    Ok(true)
+}
+
+/// an api key stored before accounts existed, or while the store was locked
+/// during the migration, becomes an account here
+async fn migrate_legacy_key(app: &tauri::AppHandle) -> Result<bool, Error> {
+    let api_key = app
+        .state::<Mutex<AppState>>()
+        .lock()
+        .credentials
+        .get(credentials::API_KEY)?;
+    let Some(api_key) = api_key else {
+        return Ok(false);
+    };
+    info!("migrate legacy api key");
+    let zotero = add_account(app, Endpoint::default(), api_key.clone()).await?;
+
+    let state = app.state::<Mutex<AppState>>();
+    let mut state = state.lock();
+    state.api_key = Some(api_key);
+    state.credentials.remove(credentials::API_KEY)?;
+    state.credentials.rename(
+        credentials::WEBDAV_PASSWORD,
+        &webdav_password_name(zotero.user_id()),
+    )?;
+    Ok(true)
+}
+
+/// check the cached key against the api. a revoked key logs the user out,
+/// network failures keep the cached state.
+async fn revalidate(app: tauri::AppHandle, endpoint: Endpoint, api_key: Secret) {
+    match Zotero::key_info(&endpoint, &api_key).await {
+        Ok(info) => {
+            let state = app.state::<Mutex<AppState>>();
+            let mut state = state.lock();
+            if state.accounts.get(info.user_id).is_none() {
+                return;
+            }
+            let path = state.accounts.dir(info.user_id).join(KEY_INFO_FILE);
+            if let Err(e) = info.save(&path) {
+                error!("save key info failed: {:?}", e);
+            }
+            if state.api_key.as_ref().map(|x| x.as_ref()) == Some(api_key.as_ref()) {
+                state.zotero = Some(Zotero::from_key_info(endpoint, api_key, &info));
+            }
+        }
+        Err(e) if e.is_revoked() => {
+            warn!("api key revoked: {}", e);
+            {
+                let state = app.state::<Mutex<AppState>>();
+                let mut state = state.lock();
+                if state.api_key.as_ref().map(|x| x.as_ref()) != Some(api_key.as_ref()) {
+                    return;
+                }
+                if let Some(user_id) = state.accounts.current {
+                    if let Err(e) = remove_account(&mut state, user_id, false) {
+                        error!("log out failed: {:?}", e);
+                    }
+                }
+            }
+            if let Err(e) = app.emit(LOGOUT_EVENT, e.to_string()) {
+                error!("emit logout failed: {:?}", e);
+            }
         }
-        Ok(true)
-    } else {
-        Ok(false)
+        Err(e) => info!("revalidate api key failed, keep cached login: {}", e),
     }
 }
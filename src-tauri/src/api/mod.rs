@@ -1,6 +1,18 @@
+pub mod account;
+pub mod base_directory;
+pub mod cache;
+pub mod capabilities;
+pub mod credentials;
 pub mod download_pdf;
+pub mod get_attachments;
 pub mod get_collections;
+pub mod get_item_detail;
 pub mod get_items;
+pub mod is_login;
 pub mod login;
+pub mod oauth;
+pub mod pin;
 pub mod refresh;
-pub mod is_login;
\ No newline at end of file
+pub mod search;
+pub mod settings;
+pub mod transfers;
@@ -0,0 +1,90 @@
+use std::time::Duration;
+
+use parking_lot::Mutex;
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Manager};
+use tauri_plugin_shell::ShellExt;
+use tracing::{error, info};
+
+use crate::api::account::add_account;
+use crate::dal::zotero::error::ZoteroError;
+use crate::dal::zotero::oauth::{CallbackServer, OAuth, RequestToken};
+use crate::dal::zotero::Endpoint;
+use crate::error::Error;
+use crate::AppState;
+
+/// emitted with an [`OAuthResult`] when the flow started by [`start_oauth`] ends
+pub const OAUTH_EVENT: &str = "oauth";
+/// the user has this long to grant access in the browser
+const AUTHORIZE_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OAuthResult {
+    pub user_name: Option<String>,
+    pub error: Option<String>,
+}
+
+/// open the zotero authorize page in the browser. once access is granted it
+/// redirects to a loopback server of the app, and the new api key is logged in
+/// like one given to `login`.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn start_oauth(app: AppHandle) -> Result<String, Error> {
+    let oauth = OAuth::zotero()?;
+    let server = CallbackServer::bind()
+        .await
+        .map_err(|e| ZoteroError::OAuth(e.to_string()))?;
+    let callback = server
+        .url()
+        .map_err(|e| ZoteroError::OAuth(e.to_string()))?;
+    let token = oauth.request_token(&callback).await?;
+    let url = oauth.authorize_url(&token);
+    info!("oauth waiting for the redirect to {}", callback);
+
+    let handle = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let result =
+            match tokio::time::timeout(AUTHORIZE_TIMEOUT, finish(&handle, &oauth, &server, &token))
+                .await
+            {
+                Ok(Ok(user_name)) => OAuthResult {
+                    user_name: Some(user_name),
+                    error: None,
+                },
+                Ok(Err(e)) => {
+                    error!("oauth login failed: {:?}", e);
+                    OAuthResult {
+                        user_name: None,
+                        error: Some(e.to_string()),
+                    }
+                }
+                Err(_) => OAuthResult {
+                    user_name: None,
+                    error: Some("authorization timed out".to_string()),
+                },
+            };
+        if let Err(e) = handle.emit(OAUTH_EVENT, result) {
+            error!("emit oauth result failed: {:?}", e);
+        }
+    });
+
+    app.shell().open(&url, None)?;
+    Ok(url)
+}
+
+async fn finish(
+    app: &AppHandle,
+    oauth: &OAuth,
+    server: &CallbackServer,
+    token: &RequestToken,
+) -> Result<String, Error> {
+    let verifier = server.wait(token).await?;
+    let access = oauth.access_token(token, &verifier).await?;
+    info!(
+        "oauth granted a key for {} ({})",
+        access.user_name, access.user_id
+    );
+
+    add_account(app, Endpoint::default(), access.api_key.clone()).await?;
+    app.state::<Mutex<AppState>>().lock().api_key = Some(access.api_key);
+    Ok(access.user_name)
+}
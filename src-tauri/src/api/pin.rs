@@ -0,0 +1,120 @@
+use parking_lot::Mutex;
+use serde::Serialize;
+use tauri::State;
+use tracing::info;
+
+use crate::dal::zotero::error::ZoteroError;
+use crate::dal::zotero::model::Feature;
+use crate::error::Error;
+use crate::model::account::PINS_FILE;
+use crate::storage;
+use crate::storage::pin::Pins;
+use crate::storage::sync_state::SyncState;
+use crate::transfer::TransferStatus;
+use crate::AppState;
+
+#[derive(Debug, Serialize)]
+pub struct PinStatus {
+    pub collection_key: String,
+    pub name: String,
+    pub recursive: bool,
+    pub attachments: usize,
+    pub downloaded: usize,
+    /// queued or running transfers
+    pub pending: usize,
+    /// bytes, remote size of all attachments
+    pub total_size: u64,
+    /// bytes, size of the downloaded files
+    pub downloaded_size: u64,
+}
+
+/// keep every attachment of a collection downloaded
+#[tauri::command(rename_all = "snake_case")]
+pub async fn pin_collection(
+    collection_key: &str,
+    recursive: bool,
+    state: State<'_, Mutex<AppState>>,
+) -> Result<(), Error> {
+    info!(
+        "pin collection: {}, recursive: {}",
+        collection_key, recursive
+    );
+    let mut state = state.lock();
+    state.pins.pin(collection_key, recursive);
+    state.pins.save(&pins_path(&state)?)?;
+    if let Some(data) = &state.data {
+        if state.check(Feature::Download).is_ok() {
+            state.pins.sync(data, &state.transfers);
+        }
+    }
+    Ok(())
+}
+
+#[tauri::command(rename_all = "snake_case")]
+pub async fn unpin_collection(
+    collection_key: &str,
+    state: State<'_, Mutex<AppState>>,
+) -> Result<(), Error> {
+    info!("unpin collection: {}", collection_key);
+    let mut state = state.lock();
+    state.pins.unpin(collection_key);
+    state.pins.save(&pins_path(&state)?)?;
+    Ok(())
+}
+
+#[tauri::command(rename_all = "snake_case")]
+pub async fn get_pin_status(state: State<'_, Mutex<AppState>>) -> Result<Vec<PinStatus>, Error> {
+    let state = state.lock();
+    let data = state.data.as_ref().ok_or(ZoteroError::NoData)?;
+    let transfers = state.transfers.list();
+
+    Ok(state
+        .pins
+        .pins
+        .iter()
+        .map(|pin| {
+            let attachments = Pins::attachments(pin, data);
+            let mut status = PinStatus {
+                collection_key: pin.collection_key.clone(),
+                name: data
+                    .find_collection(&pin.collection_key)
+                    .map(|x| x.name.clone())
+                    .unwrap_or_default(),
+                recursive: pin.recursive,
+                attachments: attachments.len(),
+                downloaded: 0,
+                pending: 0,
+                total_size: 0,
+                downloaded_size: 0,
+            };
+            for item in attachments {
+                status.total_size += item
+                    .links
+                    .enclosure
+                    .as_ref()
+                    .and_then(|x| x.length)
+                    .unwrap_or_default() as u64;
+                let size = storage::attachment_file_path(item)
+                    .and_then(|x| std::fs::metadata(x).ok())
+                    .map(|x| x.len());
+                let verified = SyncState::load(&item.key).is_some_and(|x| x.verified);
+                if let (Some(size), true) = (size, verified) {
+                    status.downloaded += 1;
+                    status.downloaded_size += size;
+                }
+                if transfers.iter().any(|x| {
+                    x.key == item.key
+                        && matches!(x.status, TransferStatus::Queued | TransferStatus::Running)
+                }) {
+                    status.pending += 1;
+                }
+            }
+            status
+        })
+        .collect())
+}
+
+fn pins_path(state: &AppState) -> Result<std::path::PathBuf, Error> {
+    let dir = state.accounts.current_dir().ok_or(ZoteroError::NotLogin)?;
+    Ok(dir.join(PINS_FILE))
+}
@@ -1,112 +1,163 @@
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
-use ahash::{AHashMap, AHashSet};
+use ahash::AHashMap;
 use parking_lot::Mutex;
-use tauri::State;
-use tracing::{error, info};
+use tauri::{AppHandle, Manager};
+use tracing::{debug, error, info};
 
+use crate::api::search;
+use crate::dal::library::desktop::Desktop;
+use crate::dal::library::{Changes, Library};
 use crate::dal::zotero::api::collection::model::Collection;
 use crate::dal::zotero::api::item::model::Item;
 use crate::dal::zotero::error::ZoteroError;
+use crate::dal::zotero::model::Feature;
 use crate::dal::zotero::Zotero;
 use crate::error::Error;
+use crate::model::account::{LIBRARY_FILE, SNAPSHOT_FILE};
 use crate::model::zotero_data::{CollectionsData, Data, SimpleItemData, EMPTY_COLLECTION_KEY};
+use crate::transfer::watcher;
 use crate::AppState;
 
 #[tauri::command(rename_all = "snake_case")]
-pub async fn refresh(state: State<'_, Mutex<AppState>>) -> Result<(), Error> {
+pub async fn refresh(app: tauri::AppHandle) -> Result<(), Error> {
     info!("refreshing zotero data");
+    reload(&app).await
+}
 
-    let zotero = state.lock().zotero.clone();
+/// fetch the library of the current account and replace the data in the state
+pub(crate) async fn reload(app: &AppHandle) -> Result<(), Error> {
+    let state = app.state::<Mutex<AppState>>();
+    let (zotero, account, dir, notes) = {
+        let state = state.lock();
+        (
+            state.zotero.clone(),
+            state.accounts.current().cloned(),
+            state.accounts.current_dir(),
+            state.check(Feature::Notes).is_ok(),
+        )
+    };
+    let (Some(account), Some(dir)) = (account, dir) else {
+        return Err(Error::Zotero(ZoteroError::NotLogin));
+    };
 
-    if let Some(zotero) = zotero {
-        state.lock().data = Some(get_data(zotero).await?);
-        Ok(())
-    } else {
-        Err(Error::Zotero(ZoteroError::NotLogin))
+    let data = match (account.database, zotero) {
+        (Some(database), _) => import_database(database, dir.join(SNAPSHOT_FILE)).await?,
+        (None, Some(zotero)) => get_data(zotero, dir.join(LIBRARY_FILE), notes).await?,
+        (None, None) => return Err(Error::Zotero(ZoteroError::NotLogin)),
+    };
+    let mut state = state.lock();
+    // the account may have been switched while fetching
+    if state.accounts.current != Some(account.user_id) {
+        return Ok(());
+    }
+    if state.check(Feature::Download).is_ok() {
+        state.pins.sync(&data, &state.transfers);
     }
+    state.data = Some(data);
+    drop(state);
+    watcher::scan(app.clone());
+    search::update_index(app);
+    Ok(())
 }
 
-async fn get_data(client: Zotero) -> Result<Data, Error> {
-    let c1 = client.clone();
-    let collections = tokio::spawn(async move {
-        let collections = c1.get_all_collections().await?;
-        Ok::<_, Error>(parse_collections(collections, AHashSet::new()))
-    });
-    let items = tokio::spawn(async move {
-        let items = client.get_all_items().await?;
-        Ok::<_, Error>(parse_items(items))
-    });
+/// the library stored by the last refresh, so it can be shown before the network answers
+pub fn load_cached(path: &Path) -> Result<Option<Data>, Error> {
+    let library = Library::open(path)?;
+    if library.version()? == 0 {
+        return Ok(None);
+    }
+    let (collections, items) = library.load()?;
+    info!(
+        "loaded cached library, {} collections, {} items",
+        collections.len(),
+        items.len()
+    );
+    Ok(Some(build_data(collections, items)))
+}
 
-    let (collections, items) = tokio::try_join!(collections, items)?;
-    let collections = collections?;
-    let items = items?;
-    Ok(Data {
-        collections: Arc::new(collections),
-        items: Arc::new(items.0),
-        collections_item_map: Arc::new(items.1),
+/// read the whole library from a fresh snapshot of a desktop database
+async fn import_database(database: PathBuf, snapshot: PathBuf) -> Result<Data, Error> {
+    tokio::task::spawn_blocking(move || -> Result<Data, Error> {
+        let (collections, items) = Desktop::snapshot(&database, &snapshot)?.load()?;
+        info!(
+            "read zotero database, {} collections, {} items",
+            collections.len(),
+            items.len()
+        );
+        Ok(build_data(collections, items))
     })
+    .await?
 }
 
-fn parse_collections(collections: Vec<Collection>, next: AHashSet<String>) -> Vec<CollectionsData> {
-    if collections.is_empty() {
-        return vec![];
-    }
-    let mut roots = vec![];
-    let mut rest_collections = vec![];
-    let mut index = AHashMap::new();
-    for collection in collections {
-        if collection.data.parent_collection.is_none() || next.contains(&collection.data.key) {
-            index.insert(collection.data.key.clone(), roots.len());
-            roots.push(CollectionsData {
-                key: collection.data.key,
-                name: collection.data.name,
-                children: None,
-                father: collection.data.parent_collection,
-            });
-        } else {
-            rest_collections.push(collection);
-        }
-    }
-    let mut next = AHashSet::new();
-    for r in &rest_collections {
-        if let Some(father) = &r.data.parent_collection {
-            if index.contains_key(father) {
-                next.insert(r.key.clone());
-            }
-        } else {
-            error!("collection has no father: {}", r.data.key);
-        }
-    }
+/// fetch what changed since the stored library version, store it and rebuild the data.
+/// notes are left out without `notes`, the key may have read them before.
+async fn get_data(client: Zotero, path: PathBuf, notes: bool) -> Result<Data, Error> {
+    let user_id = client.user_id();
+    let p1 = path.clone();
+    let since =
+        tokio::task::spawn_blocking(move || Library::open(&p1)?.version_for(user_id)).await??;
+    // the local api of zotero desktop is cheap to read in full, so it does not rely
+    // on incremental requests
+    let since = if client.is_local() { 0 } else { since };
+    debug!("library version: {}", since);
 
-    let children = parse_collections(rest_collections, next);
-    for child in children {
-        if let Some(father) = &child.father {
-            if let Some(father_index) = index.get(father) {
-                roots[*father_index]
-                    .children
-                    .get_or_insert_with(Vec::new)
-                    .push(child);
-            } else {
-                error!(
-                    "father collection not found: {} now key: {}",
-                    father, child.key
-                );
-            }
-        } else {
-            error!("children collection has no father: {}", child.key);
+    let c1 = client.clone();
+    let c2 = client.clone();
+    let collections = tokio::spawn(async move { c1.get_collections_since(since).await });
+    let items = tokio::spawn(async move { c2.get_items_since(since).await });
+    let deleted = tokio::spawn(async move {
+        if since == 0 {
+            return Ok(None);
         }
-    }
+        client.get_deleted(since).await.map(Some)
+    });
 
-    for r in &mut roots {
-        if let Some(children) = r.children.as_mut() {
-            children.sort_by(|a, b| a.name.cmp(&b.name));
-        }
-    }
+    let (collections, items, deleted) = tokio::try_join!(collections, items, deleted)?;
+    let (collections, items, deleted) = (collections?, items?, deleted?);
+    let deleted_version = deleted.as_ref().map_or(i64::MAX, |x| x.version);
+    let deleted = deleted.map(|x| x.data).unwrap_or_default();
+    let changes = Changes {
+        user_id,
+        // the responses may come from different versions if the library changed
+        // in between, the oldest one is kept so nothing is skipped next time
+        version: collections.version.min(items.version).min(deleted_version),
+        full: since == 0,
+        collections: collections.data,
+        items: items.data,
+        deleted_collections: deleted.collections,
+        deleted_items: deleted.items,
+    };
+    info!(
+        "library changes since {}: {} collections, {} items, {} deleted",
+        since,
+        changes.collections.len(),
+        changes.items.len(),
+        changes.deleted_collections.len() + changes.deleted_items.len()
+    );
 
-    roots.sort_by(|a, b| a.name.cmp(&b.name));
+    tokio::task::spawn_blocking(move || -> Result<Data, Error> {
+        let mut library = Library::open(&path)?;
+        library.apply(&changes)?;
+        let (collections, mut items) = library.load()?;
+        if !notes {
+            items.retain(|x| !x.is_note());
+        }
+        Ok(build_data(collections, items))
+    })
+    .await?
+}
 
-    roots
+fn build_data(collections: Vec<Collection>, items: Vec<Item>) -> Data {
+    let items = parse_items(items);
+    let collections = CollectionsData::tree(collections, &items.1);
+    Data {
+        collections: Arc::new(collections),
+        items: Arc::new(items.0),
+        collections_item_map: Arc::new(items.1),
+        listings: Default::default(),
+    }
 }
 
 #[allow(clippy::type_complexity)]
@@ -192,9 +243,12 @@ mod tests {
         let test_collection_data =
             std::fs::read_to_string(dotenv!("TEST_COLLECTION_DATA_PATH")).unwrap();
         let collections: Vec<Collection> = serde_json::from_str(&test_collection_data).unwrap();
-        let parsed = parse_collections(collections, AHashSet::new());
+        let parsed = CollectionsData::tree(collections, &AHashMap::new());
 
-        println!("{}", serde_json::to_string(&parsed).unwrap());
+        println!(
+            "{}",
+            serde_json::to_string(&CollectionsData::flatten(&parsed)).unwrap()
+        );
     }
 
     #[cfg(feature = "__local_test__")]
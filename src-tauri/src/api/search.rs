@@ -0,0 +1,210 @@
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use parking_lot::Mutex;
+use tauri::{AppHandle, Manager, State};
+use tracing::{debug, error, info};
+
+use crate::dal::library::search::{parse_query, Document, Hit, SearchIndex};
+use crate::dal::zotero::api::item::model::{Item, LinkMode};
+use crate::dal::zotero::error::ZoteroError;
+use crate::error::Error;
+use crate::model::account::SEARCH_FILE;
+use crate::storage::{self, fulltext};
+use crate::AppState;
+
+const DEFAULT_LIMIT: usize = 50;
+
+/// updates of the index run one after another
+static INDEXING: Mutex<()> = parking_lot::const_mutex(());
+
+/// search the items of the current account without the network, see
+/// [`parse_query`] for what `query` may contain
+#[tauri::command(rename_all = "snake_case")]
+pub async fn search(
+    query: String,
+    limit: Option<usize>,
+    state: State<'_, Mutex<AppState>>,
+) -> Result<Vec<Hit>, Error> {
+    let Some(query) = parse_query(&query) else {
+        return Ok(vec![]);
+    };
+    let dir = state
+        .lock()
+        .accounts
+        .current_dir()
+        .ok_or(ZoteroError::NotLogin)?;
+    tokio::task::spawn_blocking(move || -> Result<_, Error> {
+        let index = SearchIndex::open(&dir.join(SEARCH_FILE))?;
+        Ok(index.search(&query, limit.unwrap_or(DEFAULT_LIMIT))?)
+    })
+    .await?
+}
+
+/// bring the index of the current account up to date with its data in the
+/// background, the text of attachments is only read when their file changed
+pub(crate) fn update_index(app: &AppHandle) {
+    let (items, dir, base_directory, user_id) = {
+        let state = app.state::<Mutex<AppState>>();
+        let state = state.lock();
+        let (Some(data), Some(dir), Some(user_id)) = (
+            state.data.as_ref(),
+            state.accounts.current_dir(),
+            state.accounts.current,
+        ) else {
+            return;
+        };
+        (
+            data.items.clone(),
+            dir,
+            state.base_directory.clone(),
+            user_id,
+        )
+    };
+    let app = app.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let _guard = INDEXING.lock();
+        let result = (|| -> Result<(), Error> {
+            let mut index = SearchIndex::open(&dir.join(SEARCH_FILE))?;
+            let documents: Vec<Document> = items.values().map(document).collect();
+            let written = index.sync(&documents)?;
+            info!("search index updated, {} items changed", written);
+
+            for item in items.values() {
+                // a switched account gets its own update
+                if app.state::<Mutex<AppState>>().lock().accounts.current != Some(user_id) {
+                    return Ok(());
+                }
+                let attachments = item.is_attachment().then_some(item);
+                for attachment in attachments.into_iter().chain(item.attachments()) {
+                    index_text(&mut index, attachment, &item.key, base_directory.as_deref())?;
+                }
+            }
+            Ok(())
+        })();
+        if let Err(e) = result {
+            error!("update search index failed: {:?}", e);
+        }
+    });
+}
+
+/// index the text of attachment `key` after it was downloaded
+pub(crate) fn index_attachment(app: &AppHandle, key: &str) {
+    let (items, dir, base_directory) = {
+        let state = app.state::<Mutex<AppState>>();
+        let state = state.lock();
+        let (Some(data), Some(dir)) = (state.data.as_ref(), state.accounts.current_dir()) else {
+            return;
+        };
+        (data.items.clone(), dir, state.base_directory.clone())
+    };
+    let key = key.to_string();
+    tauri::async_runtime::spawn_blocking(move || {
+        let _guard = INDEXING.lock();
+        let Some((parent, attachment)) = items.values().find_map(|item| {
+            let attachments = item.is_attachment().then_some(item);
+            attachments
+                .into_iter()
+                .chain(item.attachments())
+                .find(|x| x.key == key)
+                .map(|x| (item, x))
+        }) else {
+            return;
+        };
+        let result = SearchIndex::open(&dir.join(SEARCH_FILE))
+            .map_err(Error::from)
+            .and_then(|mut index| {
+                index_text(
+                    &mut index,
+                    attachment,
+                    &parent.key,
+                    base_directory.as_deref(),
+                )
+            });
+        if let Err(e) = result {
+            error!("index attachment {} failed: {:?}", key, e);
+        }
+    });
+}
+
+/// read the attachment's file when it changed since its text was stored
+fn index_text(
+    index: &mut SearchIndex,
+    attachment: &Item,
+    parent: &str,
+    base_directory: Option<&Path>,
+) -> Result<(), Error> {
+    let Some(path) = file_path(attachment, base_directory) else {
+        return Ok(());
+    };
+    let Some(mtime) = std::fs::metadata(&path)
+        .and_then(|x| x.modified())
+        .ok()
+        .and_then(|x| x.duration_since(UNIX_EPOCH).ok())
+        .map(|x| x.as_millis() as i64)
+    else {
+        return Ok(());
+    };
+    if index.text_mtime(&attachment.key)? == Some(mtime) {
+        return Ok(());
+    }
+    let text = fulltext::extract(&path, &attachment.data.content_type).unwrap_or_default();
+    debug!("indexed {} chars of {:?}", text.len(), path);
+    // stored even when empty, so the file is not read again until it changes
+    index.set_text(&attachment.key, parent, mtime, &text)?;
+    Ok(())
+}
+
+fn file_path(attachment: &Item, base_directory: Option<&Path>) -> Option<PathBuf> {
+    match attachment.data.link_mode {
+        Some(LinkMode::LinkedUrl) => None,
+        Some(LinkMode::LinkedFile) => storage::linked_file_path(attachment, base_directory).ok(),
+        _ => storage::attachment_file_path(attachment),
+    }
+}
+
+fn document(item: &Item) -> Document {
+    let notes = item
+        .is_note()
+        .then_some(item)
+        .into_iter()
+        .chain(item.notes())
+        .filter_map(|x| x.note())
+        .map(fulltext::html_text)
+        .collect::<Vec<_>>()
+        .join("\n");
+    let creators = item
+        .data
+        .creators
+        .iter()
+        .map(|x| {
+            format!("{} {}", x.first_name, x.last_name)
+                .trim()
+                .to_string()
+        })
+        .collect::<Vec<_>>()
+        .join("; ");
+    let attachments = item
+        .is_attachment()
+        .then_some(item)
+        .into_iter()
+        .chain(item.attachments())
+        .map(|x| x.key.clone())
+        .collect();
+    Document {
+        key: item.key.clone(),
+        title: item.data.title.clone().unwrap_or_default(),
+        creators,
+        abstract_note: item.data.abstract_note.clone().unwrap_or_default(),
+        tags: item
+            .data
+            .tags
+            .iter()
+            .map(|x| x.tag.as_str())
+            .collect::<Vec<_>>()
+            .join("; "),
+        notes,
+        year: item.year().map(|x| x.to_string()).unwrap_or_default(),
+        attachments,
+    }
+}
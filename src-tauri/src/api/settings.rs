@@ -0,0 +1,24 @@
+use parking_lot::Mutex;
+use tauri::{Manager, State};
+
+use crate::error::Error;
+use crate::model::settings::Settings;
+use crate::storage::cache;
+use crate::AppState;
+
+#[tauri::command(rename_all = "snake_case")]
+pub async fn get_settings(state: State<'_, Mutex<AppState>>) -> Result<Settings, Error> {
+    Ok(state.lock().settings.clone())
+}
+
+#[tauri::command(rename_all = "snake_case")]
+pub async fn set_settings(
+    settings: Settings,
+    state: State<'_, Mutex<AppState>>,
+    app: tauri::AppHandle,
+) -> Result<(), Error> {
+    settings.save(&app.path().app_data_dir().unwrap().join("settings.json"))?;
+    state.lock().settings = settings;
+    cache::trim(&app, None).await?;
+    Ok(())
+}
@@ -0,0 +1,58 @@
+use parking_lot::Mutex;
+use tauri::State;
+
+use crate::dal::zotero::error::ZoteroError;
+use crate::dal::zotero::model::Feature;
+use crate::error::Error;
+use crate::transfer::{Transfer, TransferKind};
+use crate::AppState;
+
+#[tauri::command(rename_all = "snake_case")]
+pub async fn list_transfers(state: State<'_, Mutex<AppState>>) -> Result<Vec<Transfer>, Error> {
+    Ok(state.lock().transfers.list())
+}
+
+/// queue downloads of the given attachment keys in the background
+#[tauri::command(rename_all = "snake_case")]
+pub async fn queue_downloads(
+    keys: Vec<String>,
+    state: State<'_, Mutex<AppState>>,
+) -> Result<(), Error> {
+    let state = state.lock();
+    state.check(Feature::Download)?;
+    let data = state.data.as_ref().ok_or(ZoteroError::NoData)?;
+
+    for key in keys {
+        let item = data
+            .find_attachment(&key)
+            .ok_or_else(|| ZoteroError::NoAttachment(key.clone()))?;
+        let filename = item
+            .data
+            .filename
+            .clone()
+            .ok_or_else(|| ZoteroError::NoAttachment(key.clone()))?;
+        let md5 = item.data.md5.clone();
+        state
+            .transfers
+            .enqueue(Transfer::new(key, TransferKind::Download, filename).with_md5(md5));
+    }
+    Ok(())
+}
+
+#[tauri::command(rename_all = "snake_case")]
+pub async fn cancel_transfer(key: &str, state: State<'_, Mutex<AppState>>) -> Result<(), Error> {
+    let transfers = state.lock().transfers.clone();
+    Ok(transfers.cancel(key)?)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+pub async fn pause_transfer(key: &str, state: State<'_, Mutex<AppState>>) -> Result<(), Error> {
+    let transfers = state.lock().transfers.clone();
+    Ok(transfers.pause(key)?)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+pub async fn resume_transfer(key: &str, state: State<'_, Mutex<AppState>>) -> Result<(), Error> {
+    let transfers = state.lock().transfers.clone();
+    Ok(transfers.resume(key)?)
+}
@@ -0,0 +1,197 @@
+//! secrets are encrypted by a key which never leaves the android keystore
+
+use jni::objects::{JByteArray, JObject, JValue};
+use jni::{JNIEnv, JavaVM};
+
+use super::error::CredentialError;
+
+const KEYSTORE: &str = "AndroidKeyStore";
+const KEY_ALIAS: &str = "zotero_client_credentials";
+const TRANSFORMATION: &str = "AES/GCM/NoPadding";
+const IV_LEN: usize = 12;
+const TAG_BITS: i32 = 128;
+
+// javax.crypto.Cipher
+const ENCRYPT_MODE: i32 = 1;
+const DECRYPT_MODE: i32 = 2;
+// android.security.keystore.KeyProperties
+const PURPOSE_ENCRYPT: i32 = 1;
+const PURPOSE_DECRYPT: i32 = 2;
+
+const BUILDER_CLASS: &str = "android/security/keystore/KeyGenParameterSpec$Builder";
+const BUILDER_SIG: &str = "Landroid/security/keystore/KeyGenParameterSpec$Builder;";
+
+fn keystore_error(e: jni::errors::Error) -> CredentialError {
+    CredentialError::Keystore(e.to_string())
+}
+
+fn with_env<T>(
+    f: impl FnOnce(&mut JNIEnv) -> jni::errors::Result<T>,
+) -> Result<T, CredentialError> {
+    let ctx = ndk_context::android_context();
+    let vm = unsafe { JavaVM::from_raw(ctx.vm().cast()) }.map_err(keystore_error)?;
+    let mut env = vm.attach_current_thread().map_err(keystore_error)?;
+    f(&mut env).map_err(|e| {
+        // a pending java exception would break every later jni call on this thread
+        let _ = env.exception_clear();
+        keystore_error(e)
+    })
+}
+
+/// the app's key from the keystore, created on first use
+fn secret_key<'a>(env: &mut JNIEnv<'a>) -> jni::errors::Result<JObject<'a>> {
+    let keystore_name = env.new_string(KEYSTORE)?;
+    let keystore = env
+        .call_static_method(
+            "java/security/KeyStore",
+            "getInstance",
+            "(Ljava/lang/String;)Ljava/security/KeyStore;",
+            &[JValue::Object(&keystore_name)],
+        )?
+        .l()?;
+    env.call_method(
+        &keystore,
+        "load",
+        "(Ljava/security/KeyStore$LoadStoreParameter;)V",
+        &[JValue::Object(&JObject::null())],
+    )?;
+
+    let alias = env.new_string(KEY_ALIAS)?;
+    let key = env
+        .call_method(
+            &keystore,
+            "getKey",
+            "(Ljava/lang/String;[C)Ljava/security/Key;",
+            &[JValue::Object(&alias), JValue::Object(&JObject::null())],
+        )?
+        .l()?;
+    if !key.is_null() {
+        return Ok(key);
+    }
+
+    let builder = env.new_object(
+        BUILDER_CLASS,
+        "(Ljava/lang/String;I)V",
+        &[
+            JValue::Object(&alias),
+            JValue::Int(PURPOSE_ENCRYPT | PURPOSE_DECRYPT),
+        ],
+    )?;
+    let gcm = env.new_string("GCM")?;
+    let block_modes = env.new_object_array(1, "java/lang/String", &gcm)?;
+    env.call_method(
+        &builder,
+        "setBlockModes",
+        format!("([Ljava/lang/String;){}", BUILDER_SIG),
+        &[JValue::Object(&block_modes)],
+    )?;
+    let no_padding = env.new_string("NoPadding")?;
+    let paddings = env.new_object_array(1, "java/lang/String", &no_padding)?;
+    env.call_method(
+        &builder,
+        "setEncryptionPaddings",
+        format!("([Ljava/lang/String;){}", BUILDER_SIG),
+        &[JValue::Object(&paddings)],
+    )?;
+    let spec = env
+        .call_method(
+            &builder,
+            "build",
+            "()Landroid/security/keystore/KeyGenParameterSpec;",
+            &[],
+        )?
+        .l()?;
+
+    let aes = env.new_string("AES")?;
+    let generator = env
+        .call_static_method(
+            "javax/crypto/KeyGenerator",
+            "getInstance",
+            "(Ljava/lang/String;Ljava/lang/String;)Ljavax/crypto/KeyGenerator;",
+            &[JValue::Object(&aes), JValue::Object(&keystore_name)],
+        )?
+        .l()?;
+    env.call_method(
+        &generator,
+        "init",
+        "(Ljava/security/spec/AlgorithmParameterSpec;)V",
+        &[JValue::Object(&spec)],
+    )?;
+    env.call_method(&generator, "generateKey", "()Ljavax/crypto/SecretKey;", &[])?
+        .l()
+}
+
+fn cipher<'a>(
+    env: &mut JNIEnv<'a>,
+    mode: i32,
+    iv: Option<&[u8]>,
+) -> jni::errors::Result<JObject<'a>> {
+    let key = secret_key(env)?;
+    let transformation = env.new_string(TRANSFORMATION)?;
+    let cipher = env
+        .call_static_method(
+            "javax/crypto/Cipher",
+            "getInstance",
+            "(Ljava/lang/String;)Ljavax/crypto/Cipher;",
+            &[JValue::Object(&transformation)],
+        )?
+        .l()?;
+    match iv {
+        None => env.call_method(
+            &cipher,
+            "init",
+            "(ILjava/security/Key;)V",
+            &[JValue::Int(mode), JValue::Object(&key)],
+        )?,
+        Some(iv) => {
+            let iv = env.byte_array_from_slice(iv)?;
+            let spec = env.new_object(
+                "javax/crypto/spec/GCMParameterSpec",
+                "(I[B)V",
+                &[JValue::Int(TAG_BITS), JValue::Object(&iv)],
+            )?;
+            env.call_method(
+                &cipher,
+                "init",
+                "(ILjava/security/Key;Ljava/security/spec/AlgorithmParameterSpec;)V",
+                &[
+                    JValue::Int(mode),
+                    JValue::Object(&key),
+                    JValue::Object(&spec),
+                ],
+            )?
+        }
+    };
+    Ok(cipher)
+}
+
+fn do_final(env: &mut JNIEnv, cipher: &JObject, data: &[u8]) -> jni::errors::Result<Vec<u8>> {
+    let input = env.byte_array_from_slice(data)?;
+    let output: JByteArray = env
+        .call_method(cipher, "doFinal", "([B)[B", &[JValue::Object(&input)])?
+        .l()?
+        .into();
+    env.convert_byte_array(&output)
+}
+
+/// returns iv followed by the cipher text
+pub fn seal(data: &[u8]) -> Result<Vec<u8>, CredentialError> {
+    with_env(|env| {
+        let cipher = cipher(env, ENCRYPT_MODE, None)?;
+        let iv: JByteArray = env.call_method(&cipher, "getIV", "()[B", &[])?.l()?.into();
+        let mut sealed = env.convert_byte_array(&iv)?;
+        sealed.extend(do_final(env, &cipher, data)?);
+        Ok(sealed)
+    })
+}
+
+pub fn open(sealed: &[u8]) -> Result<Vec<u8>, CredentialError> {
+    if sealed.len() < IV_LEN {
+        return Err(CredentialError::Crypto);
+    }
+    let (iv, data) = sealed.split_at(IV_LEN);
+    with_env(|env| {
+        let cipher = cipher(env, DECRYPT_MODE, Some(iv))?;
+        do_final(env, &cipher, data)
+    })
+}
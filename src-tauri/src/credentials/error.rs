@@ -0,0 +1,20 @@
+#[derive(Debug, thiserror::Error)]
+pub enum CredentialError {
+    #[error("keystore error: {0}")]
+    Keystore(String),
+
+    #[error("credentials are locked, a passphrase is required")]
+    Locked,
+
+    #[error("decrypt failed, wrong passphrase or damaged data")]
+    Crypto,
+
+    #[error("invalid stored data: {0}")]
+    Encoding(#[from] hex::FromHexError),
+
+    #[error("io: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("json: {0}")]
+    Json(#[from] serde_json::Error),
+}
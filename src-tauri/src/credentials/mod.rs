@@ -0,0 +1,317 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use error::CredentialError;
+use serde::{Deserialize, Serialize};
+use tracing::{error, info, warn};
+
+use crate::model::auth::Secret;
+
+#[cfg(target_os = "android")]
+mod android;
+pub mod error;
+
+/// single account secrets of older versions, see [`api_key_name`] and [`webdav_password_name`]
+pub const API_KEY: &str = "api_key";
+pub const WEBDAV_PASSWORD: &str = "webdav_password";
+
+const CREDENTIALS_FILE: &str = "credentials.json";
+/// written in plaintext by older versions
+const LEGACY_API_KEY_FILE: &str = "api_key";
+/// used to derive the key where no keystore is available
+pub const PASSPHRASE_ENV: &str = "ZOTERO_CLIENT_PASSPHRASE";
+#[cfg(any(target_os = "macos", target_os = "windows"))]
+const KEYRING_SERVICE: &str = "zotero_client";
+
+const NONCE_LEN: usize = 12;
+const SALT_LEN: usize = 16;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct StoreFile {
+    /// hex, salt of the passphrase derived key
+    #[serde(skip_serializing_if = "Option::is_none")]
+    salt: Option<String>,
+    /// hex encoded sealed secrets by name
+    secrets: BTreeMap<String, String>,
+}
+
+pub fn api_key_name(user_id: i64) -> String {
+    format!("{}:{}", API_KEY, user_id)
+}
+
+pub fn webdav_password_name(user_id: i64) -> String {
+    format!("{}:{}", WEBDAV_PASSWORD, user_id)
+}
+
+enum Sealer {
+    #[cfg(target_os = "android")]
+    Keystore,
+    /// aes-256-gcm with a key from the os keyring or derived from a passphrase
+    Key(Box<Aes256Gcm>),
+}
+
+impl Sealer {
+    fn seal(&self, data: &[u8]) -> Result<Vec<u8>, CredentialError> {
+        match self {
+            #[cfg(target_os = "android")]
+            Self::Keystore => android::seal(data),
+            Self::Key(cipher) => {
+                let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+                let mut sealed = nonce.to_vec();
+                sealed.extend(
+                    cipher
+                        .encrypt(&nonce, data)
+                        .map_err(|_| CredentialError::Crypto)?,
+                );
+                Ok(sealed)
+            }
+        }
+    }
+
+    fn open(&self, sealed: &[u8]) -> Result<Vec<u8>, CredentialError> {
+        match self {
+            #[cfg(target_os = "android")]
+            Self::Keystore => android::open(sealed),
+            Self::Key(cipher) => {
+                if sealed.len() < NONCE_LEN {
+                    return Err(CredentialError::Crypto);
+                }
+                let (nonce, data) = sealed.split_at(NONCE_LEN);
+                cipher
+                    .decrypt(Nonce::from_slice(nonce), data)
+                    .map_err(|_| CredentialError::Crypto)
+            }
+        }
+    }
+
+    fn from_key(key: &[u8; 32]) -> Self {
+        Self::Key(Box::new(Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key))))
+    }
+
+    fn from_passphrase(passphrase: &str, salt: &[u8]) -> Result<Self, CredentialError> {
+        let mut key = [0u8; 32];
+        argon2::Argon2::default()
+            .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+            .map_err(|e| CredentialError::Keystore(e.to_string()))?;
+        Ok(Self::from_key(&key))
+    }
+
+    /// the platform keystore, `None` when a passphrase is needed instead
+    #[cfg(target_os = "android")]
+    fn platform() -> Result<Option<Self>, CredentialError> {
+        Ok(Some(Self::Keystore))
+    }
+
+    #[cfg(any(target_os = "macos", target_os = "windows"))]
+    fn platform() -> Result<Option<Self>, CredentialError> {
+        let keystore_error = |e: keyring::Error| CredentialError::Keystore(e.to_string());
+        let entry = keyring::Entry::new(KEYRING_SERVICE, "master_key").map_err(keystore_error)?;
+        let key = match entry.get_password() {
+            Ok(x) => hex::decode(x)?,
+            Err(keyring::Error::NoEntry) => {
+                let key = Aes256Gcm::generate_key(OsRng).to_vec();
+                entry
+                    .set_password(&hex::encode(&key))
+                    .map_err(keystore_error)?;
+                key
+            }
+            Err(e) => return Err(keystore_error(e)),
+        };
+        let key: [u8; 32] = key.try_into().map_err(|_| CredentialError::Crypto)?;
+        Ok(Some(Self::from_key(&key)))
+    }
+
+    #[cfg(not(any(target_os = "android", target_os = "macos", target_os = "windows")))]
+    fn platform() -> Result<Option<Self>, CredentialError> {
+        Ok(None)
+    }
+}
+
+/// secrets encrypted at rest in the app data dir.
+///
+/// the key comes from the platform keystore. without one, as on linux, it is
+/// derived from a passphrase, taken from [`PASSPHRASE_ENV`] or given to
+/// [`CredentialStore::unlock`]. until then the store is locked.
+pub struct CredentialStore {
+    data_dir: PathBuf,
+    file: StoreFile,
+    sealer: Option<Sealer>,
+}
+
+impl CredentialStore {
+    pub fn open(data_dir: &Path) -> Self {
+        let file: StoreFile = std::fs::read_to_string(data_dir.join(CREDENTIALS_FILE))
+            .ok()
+            .and_then(|x| serde_json::from_str(&x).ok())
+            .unwrap_or_default();
+        let mut store = Self {
+            data_dir: data_dir.to_path_buf(),
+            file,
+            sealer: None,
+        };
+
+        match Sealer::platform() {
+            Ok(Some(sealer)) => store.sealer = Some(sealer),
+            Ok(None) => {
+                if let Ok(passphrase) = std::env::var(PASSPHRASE_ENV) {
+                    if let Err(e) = store.unlock(&passphrase) {
+                        error!("unlock credentials failed: {:?}", e);
+                    }
+                }
+            }
+            Err(e) => error!("open keystore failed: {:?}", e),
+        }
+        if store.sealer.is_some() {
+            store.migrate_legacy();
+        }
+        store
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.sealer.is_none()
+    }
+
+    /// derive the key from `passphrase`. a wrong passphrase is rejected when
+    /// it can not decrypt the secrets already stored.
+    pub fn unlock(&mut self, passphrase: &str) -> Result<(), CredentialError> {
+        let salt = match &self.file.salt {
+            Some(salt) => hex::decode(salt)?,
+            None => {
+                let mut salt = vec![0u8; SALT_LEN];
+                OsRng.fill_bytes(&mut salt);
+                salt
+            }
+        };
+        let sealer = Sealer::from_passphrase(passphrase, &salt)?;
+        if let Some(sealed) = self.file.secrets.values().next() {
+            sealer.open(&hex::decode(sealed)?)?;
+        }
+
+        self.file.salt = Some(hex::encode(&salt));
+        self.sealer = Some(sealer);
+        self.save()?;
+        self.migrate_legacy();
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Result<Option<Secret>, CredentialError> {
+        let Some(sealed) = self.file.secrets.get(name) else {
+            return Ok(None);
+        };
+        let sealer = self.sealer.as_ref().ok_or(CredentialError::Locked)?;
+        let data = sealer.open(&hex::decode(sealed)?)?;
+        Ok(Some(
+            String::from_utf8(data)
+                .map_err(|_| CredentialError::Crypto)?
+                .into(),
+        ))
+    }
+
+    pub fn set(&mut self, name: &str, secret: &Secret) -> Result<(), CredentialError> {
+        let sealer = self.sealer.as_ref().ok_or(CredentialError::Locked)?;
+        let sealed = sealer.seal(secret.as_ref().as_bytes())?;
+        self.file
+            .secrets
+            .insert(name.to_string(), hex::encode(sealed));
+        self.save()
+    }
+
+    /// store the secret under another name
+    pub fn rename(&mut self, from: &str, to: &str) -> Result<(), CredentialError> {
+        if let Some(secret) = self.get(from)? {
+            self.set(to, &secret)?;
+            self.remove(from)?;
+        }
+        Ok(())
+    }
+
+    pub fn remove(&mut self, name: &str) -> Result<(), CredentialError> {
+        if self.file.secrets.remove(name).is_some() {
+            self.save()?;
+        }
+        Ok(())
+    }
+
+    fn save(&self) -> Result<(), CredentialError> {
+        std::fs::write(
+            self.data_dir.join(CREDENTIALS_FILE),
+            serde_json::to_string(&self.file)?,
+        )?;
+        Ok(())
+    }
+
+    /// encrypt the plaintext api key of older versions and delete the file
+    fn migrate_legacy(&mut self) {
+        let path = self.data_dir.join(LEGACY_API_KEY_FILE);
+        let Ok(api_key) = std::fs::read_to_string(&path) else {
+            return;
+        };
+        if !self.file.secrets.contains_key(API_KEY) {
+            if let Err(e) = self.set(API_KEY, &api_key.trim().into()) {
+                error!("migrate api key failed: {:?}", e);
+                return;
+            }
+        }
+        match std::fs::remove_file(&path) {
+            Ok(()) => info!("plaintext api key migrated to the credential store"),
+            Err(e) => warn!("remove plaintext api key failed: {:?}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("zotero-credentials-{}", name));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn passphrase_store(dir: &Path, passphrase: &str) -> CredentialStore {
+        let mut store = CredentialStore {
+            data_dir: dir.to_path_buf(),
+            file: Default::default(),
+            sealer: None,
+        };
+        if let Ok(x) = std::fs::read_to_string(dir.join(CREDENTIALS_FILE)) {
+            store.file = serde_json::from_str(&x).unwrap();
+        }
+        store.unlock(passphrase).unwrap();
+        store
+    }
+
+    #[test]
+    fn test_passphrase_store() {
+        let dir = test_dir("passphrase");
+        std::fs::write(dir.join(LEGACY_API_KEY_FILE), "legacy-key\n").unwrap();
+
+        let mut store = passphrase_store(&dir, "secret");
+        assert!(!dir.join(LEGACY_API_KEY_FILE).exists());
+        assert_eq!(store.get(API_KEY).unwrap().unwrap().as_ref(), "legacy-key");
+        store.set(WEBDAV_PASSWORD, &"dav".into()).unwrap();
+
+        let stored = std::fs::read_to_string(dir.join(CREDENTIALS_FILE)).unwrap();
+        assert!(!stored.contains("legacy-key"));
+
+        let store = passphrase_store(&dir, "secret");
+        assert_eq!(store.get(WEBDAV_PASSWORD).unwrap().unwrap().as_ref(), "dav");
+
+        let mut store = CredentialStore {
+            data_dir: dir.clone(),
+            file: serde_json::from_str(&stored).unwrap(),
+            sealer: None,
+        };
+        assert!(matches!(store.get(API_KEY), Err(CredentialError::Locked)));
+        assert!(matches!(
+            store.unlock("wrong"),
+            Err(CredentialError::Crypto)
+        ));
+    }
+}
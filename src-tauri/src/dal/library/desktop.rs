@@ -0,0 +1,438 @@
+use std::path::Path;
+
+use ahash::AHashMap;
+use rusqlite::{Connection, OpenFlags, OptionalExtension};
+use serde_json::Value;
+
+use super::error::LibraryError;
+use crate::dal::zotero::api::collection::model::{Collection, Data as CollectionData};
+use crate::dal::zotero::api::item::model::{Creator, Item, ItemData, LinkMode, Tag};
+
+/// account id of a library read from a desktop database, it has no zotero user
+pub const DESKTOP_USER_ID: i64 = -1;
+
+/// prefix of the `path` of attachments kept in the desktop storage directory
+const STORAGE_PREFIX: &str = "storage:";
+
+/// the `zotero.sqlite` of zotero desktop, read without any network.
+///
+/// only the user library is read, items and collections in the trash are left out.
+pub struct Desktop {
+    conn: Connection,
+}
+
+impl Desktop {
+    /// copy `database` to `snapshot` and open the copy, so a running zotero, which
+    /// keeps its database locked, is never waited for or disturbed
+    pub fn snapshot(database: &Path, snapshot: &Path) -> Result<Self, LibraryError> {
+        std::fs::copy(database, snapshot)?;
+        // the index of an older snapshot's log would not match the new one
+        std::fs::remove_file(suffixed(snapshot, "-shm")).ok();
+        // changes not yet checkpointed are only in the write ahead log
+        match std::fs::copy(suffixed(database, "-wal"), suffixed(snapshot, "-wal")) {
+            Ok(_) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                std::fs::remove_file(suffixed(snapshot, "-wal")).ok();
+            }
+            Err(e) => return Err(e.into()),
+        }
+        Self::open(snapshot)
+    }
+
+    pub fn open(path: &Path) -> Result<Self, LibraryError> {
+        let conn = Connection::open_with_flags(
+            path,
+            OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+        )?;
+        Ok(Self { conn })
+    }
+
+    /// name of the zotero account the desktop syncs with, if any
+    pub fn user_name(&self) -> Option<String> {
+        self.conn
+            .query_row(
+                "SELECT value FROM settings WHERE setting = 'account' AND key = 'username'",
+                [],
+                |row| row.get(0),
+            )
+            .optional()
+            .ok()
+            .flatten()
+    }
+
+    /// collections and items shaped like the ones of the web api
+    pub fn load(&self) -> Result<(Vec<Collection>, Vec<Item>), LibraryError> {
+        let library: i64 = self.conn.query_row(
+            "SELECT libraryID FROM libraries WHERE type = 'user'",
+            [],
+            |row| row.get(0),
+        )?;
+        Ok((self.collections(library)?, self.items(library)?))
+    }
+
+    fn collections(&self, library: i64) -> Result<Vec<Collection>, LibraryError> {
+        let mut stmt = self.conn.prepare(
+            "SELECT c.key, c.version, c.collectionName, p.key FROM collections c
+             LEFT JOIN collections p ON p.collectionID = c.parentCollectionID
+             WHERE c.libraryID = ?1
+             AND c.collectionID NOT IN (SELECT collectionID FROM deletedCollections)",
+        )?;
+        let rows = stmt.query_map([library], |row| {
+            let key: String = row.get(0)?;
+            Ok(Collection {
+                key: key.clone(),
+                version: row.get(1)?,
+                data: CollectionData {
+                    key,
+                    version: row.get(1)?,
+                    name: row.get(2)?,
+                    parent_collection: row.get(3)?,
+                },
+                ..Default::default()
+            })
+        })?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    fn items(&self, library: i64) -> Result<Vec<Item>, LibraryError> {
+        let mut items = AHashMap::new();
+        let mut stmt = self.conn.prepare(
+            "SELECT i.itemID, i.key, i.version, t.typeName, i.dateAdded, i.dateModified
+             FROM items i JOIN itemTypes t USING (itemTypeID)
+             WHERE i.libraryID = ?1 AND t.typeName != 'annotation'
+             AND i.itemID NOT IN (SELECT itemID FROM deletedItems)",
+        )?;
+        let mut rows = stmt.query([library])?;
+        while let Some(row) = rows.next()? {
+            let key: String = row.get(1)?;
+            let version = row.get(2)?;
+            let item = Item {
+                key: key.clone(),
+                version,
+                data: ItemData {
+                    key,
+                    version,
+                    item_type: row.get(3)?,
+                    date_added: timestamp(&row.get::<_, String>(4)?),
+                    date_modified: timestamp(&row.get::<_, String>(5)?),
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            items.insert(row.get::<_, i64>(0)?, item);
+        }
+
+        self.each(
+            "SELECT d.itemID, f.fieldName, b.fieldName, v.value FROM itemData d
+             JOIN items i USING (itemID)
+             JOIN fields f USING (fieldID)
+             JOIN itemDataValues v USING (valueID)
+             LEFT JOIN baseFieldMappings m
+                ON m.itemTypeID = i.itemTypeID AND m.fieldID = d.fieldID
+             LEFT JOIN fields b ON b.fieldID = m.baseFieldID",
+            &mut items,
+            |item, row| {
+                let field: String = row.get(1)?;
+                let base: Option<String> = row.get(2)?;
+                // values keep the sqlite type they were written with
+                let value = match row.get::<_, rusqlite::types::Value>(3)? {
+                    rusqlite::types::Value::Text(x) => x,
+                    rusqlite::types::Value::Integer(x) => x.to_string(),
+                    rusqlite::types::Value::Real(x) => x.to_string(),
+                    _ => return Ok(()),
+                };
+                // type specific fields such as `caseName` stand in for the title
+                match base.as_deref().unwrap_or(&field) {
+                    "title" => item.data.title = Some(value.clone()),
+                    "abstractNote" => item.data.abstract_note = Some(value.clone()),
+                    "url" => item.data.url = Some(value.clone()),
+                    "accessDate" => item.data.access_date = Some(timestamp(&value)),
+                    _ => {}
+                }
+                if !matches!(
+                    field.as_str(),
+                    "title" | "abstractNote" | "url" | "accessDate"
+                ) {
+                    item.data.extra_fields.insert(field, Value::String(value));
+                }
+                Ok(())
+            },
+        )?;
+
+        self.each(
+            "SELECT ic.itemID, ct.creatorType, c.firstName, c.lastName FROM itemCreators ic
+             JOIN creators c USING (creatorID)
+             JOIN creatorTypes ct USING (creatorTypeID)
+             ORDER BY ic.itemID, ic.orderIndex",
+            &mut items,
+            |item, row| {
+                item.data.creators.push(Creator {
+                    creator_type: row.get(1)?,
+                    first_name: row.get::<_, Option<String>>(2)?.unwrap_or_default(),
+                    last_name: row.get::<_, Option<String>>(3)?.unwrap_or_default(),
+                });
+                Ok(())
+            },
+        )?;
+
+        self.each(
+            "SELECT it.itemID, t.name, it.type FROM itemTags it JOIN tags t USING (tagID)",
+            &mut items,
+            |item, row| {
+                item.data.tags.push(Tag {
+                    tag: row.get(1)?,
+                    tag_type: row.get::<_, Option<i32>>(2)?.filter(|x| *x != 0),
+                });
+                Ok(())
+            },
+        )?;
+
+        self.each(
+            "SELECT a.itemID, p.key, a.linkMode, a.contentType, a.path,
+                a.storageModTime, a.storageHash
+             FROM itemAttachments a LEFT JOIN items p ON p.itemID = a.parentItemID",
+            &mut items,
+            |item, row| {
+                item.data.parent_item = row.get(1)?;
+                item.data.link_mode = row.get::<_, Option<i64>>(2)?.map(link_mode);
+                item.data.content_type = row.get::<_, Option<String>>(3)?.unwrap_or_default();
+                item.data.mtime = row.get(5)?;
+                item.data.md5 = row.get(6)?;
+                let path: Option<String> = row.get(4)?;
+                match path {
+                    Some(path) if path.starts_with(STORAGE_PREFIX) => {
+                        item.data.filename = Some(path[STORAGE_PREFIX.len()..].to_string());
+                    }
+                    Some(path) => {
+                        item.data.filename = Path::new(&path)
+                            .file_name()
+                            .map(|x| x.to_string_lossy().into_owned());
+                        item.data.path = Some(path);
+                    }
+                    None => {}
+                }
+                Ok(())
+            },
+        )?;
+
+        self.each(
+            "SELECT n.itemID, p.key, n.note, n.title FROM itemNotes n
+             LEFT JOIN items p ON p.itemID = n.parentItemID",
+            &mut items,
+            |item, row| {
+                item.data.parent_item = row.get(1)?;
+                if let Some(note) = row.get::<_, Option<String>>(2)? {
+                    item.data
+                        .extra_fields
+                        .insert("note".to_string(), Value::String(note));
+                }
+                item.data.title = row.get(3)?;
+                Ok(())
+            },
+        )?;
+
+        self.each(
+            "SELECT r.itemID, p.predicate, r.object FROM itemRelations r
+             JOIN relationPredicates p USING (predicateID)",
+            &mut items,
+            |item, row| {
+                item.data
+                    .relations
+                    .entry(row.get(1)?)
+                    .or_default()
+                    .push(row.get(2)?);
+                Ok(())
+            },
+        )?;
+
+        // like the web api, only top level items name their collections
+        for item in items.values_mut() {
+            if item.data.parent_item.is_none() {
+                item.data.collections = Some(vec![]);
+            }
+        }
+        self.each(
+            "SELECT ci.itemID, c.key FROM collectionItems ci JOIN collections c USING (collectionID)",
+            &mut items,
+            |item, row| {
+                if let Some(collections) = item.data.collections.as_mut() {
+                    collections.push(row.get(1)?);
+                }
+                Ok(())
+            },
+        )?;
+
+        // children of trashed items are not trashed themselves
+        let keys: ahash::AHashSet<String> = items.values().map(|x| x.key.clone()).collect();
+        Ok(items
+            .into_values()
+            .filter(|x| x.data.parent_item.as_ref().is_none_or(|x| keys.contains(x)))
+            .collect())
+    }
+
+    /// run `sql`, whose first column is an item id, and hand every row of a loaded
+    /// item to `f`
+    fn each(
+        &self,
+        sql: &str,
+        items: &mut AHashMap<i64, Item>,
+        mut f: impl FnMut(&mut Item, &rusqlite::Row) -> rusqlite::Result<()>,
+    ) -> Result<(), LibraryError> {
+        let mut stmt = self.conn.prepare(sql)?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            if let Some(item) = items.get_mut(&row.get::<_, i64>(0)?) {
+                f(item, row)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// sqlite keeps its log and index next to the database, named with a suffix
+fn suffixed(path: &Path, suffix: &str) -> std::path::PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(suffix);
+    path.into()
+}
+
+/// `2024-01-02 03:04:05` as stored by zotero, in utc, to the api's iso format
+fn timestamp(value: &str) -> String {
+    match value.split_once(' ') {
+        Some((date, time)) => format!("{}T{}Z", date, time),
+        None => value.to_string(),
+    }
+}
+
+fn link_mode(value: i64) -> LinkMode {
+    match value {
+        0 => LinkMode::ImportedFile,
+        1 => LinkMode::ImportedUrl,
+        2 => LinkMode::LinkedFile,
+        3 => LinkMode::LinkedUrl,
+        4 => LinkMode::EmbeddedImage,
+        _ => LinkMode::Unknown,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// the part of the zotero desktop schema that is read
+    const SCHEMA: &str = "
+    CREATE TABLE libraries (libraryID INTEGER PRIMARY KEY, type TEXT NOT NULL);
+    CREATE TABLE settings (setting TEXT, key TEXT, value);
+    CREATE TABLE itemTypes (itemTypeID INTEGER PRIMARY KEY, typeName TEXT);
+    CREATE TABLE fields (fieldID INTEGER PRIMARY KEY, fieldName TEXT);
+    CREATE TABLE baseFieldMappings (itemTypeID INT, baseFieldID INT, fieldID INT);
+    CREATE TABLE items (itemID INTEGER PRIMARY KEY, itemTypeID INT, dateAdded TEXT,
+        dateModified TEXT, libraryID INT, key TEXT, version INT);
+    CREATE TABLE itemDataValues (valueID INTEGER PRIMARY KEY, value);
+    CREATE TABLE itemData (itemID INT, fieldID INT, valueID INT);
+    CREATE TABLE creatorTypes (creatorTypeID INTEGER PRIMARY KEY, creatorType TEXT);
+    CREATE TABLE creators (creatorID INTEGER PRIMARY KEY, firstName TEXT, lastName TEXT);
+    CREATE TABLE itemCreators (itemID INT, creatorID INT, creatorTypeID INT, orderIndex INT);
+    CREATE TABLE tags (tagID INTEGER PRIMARY KEY, name TEXT);
+    CREATE TABLE itemTags (itemID INT, tagID INT, type INT);
+    CREATE TABLE collections (collectionID INTEGER PRIMARY KEY, collectionName TEXT,
+        parentCollectionID INT, libraryID INT, key TEXT, version INT);
+    CREATE TABLE collectionItems (collectionID INT, itemID INT, orderIndex INT);
+    CREATE TABLE itemAttachments (itemID INTEGER PRIMARY KEY, parentItemID INT,
+        linkMode INT, contentType TEXT, path TEXT, storageModTime INT, storageHash TEXT);
+    CREATE TABLE itemNotes (itemID INTEGER PRIMARY KEY, parentItemID INT, note TEXT, title TEXT);
+    CREATE TABLE relationPredicates (predicateID INTEGER PRIMARY KEY, predicate TEXT);
+    CREATE TABLE itemRelations (itemID INT, predicateID INT, object TEXT);
+    CREATE TABLE deletedItems (itemID INTEGER PRIMARY KEY);
+    CREATE TABLE deletedCollections (collectionID INTEGER PRIMARY KEY);
+
+    INSERT INTO libraries VALUES (1, 'user'), (2, 'group');
+    INSERT INTO settings VALUES ('account', 'username', 'alice');
+    INSERT INTO itemTypes VALUES (1, 'journalArticle'), (2, 'attachment'), (3, 'note'),
+        (4, 'case');
+    INSERT INTO fields VALUES (1, 'title'), (2, 'caseName'), (3, 'publicationTitle'),
+        (4, 'volume');
+    INSERT INTO baseFieldMappings VALUES (4, 1, 2);
+    INSERT INTO items VALUES
+        (1, 1, '2024-01-02 03:04:05', '2024-01-02 03:04:05', 1, 'ARTICLE', 5),
+        (2, 2, '2024-01-02 03:04:05', '2024-01-02 03:04:05', 1, 'PDF', 5),
+        (3, 3, '2024-01-02 03:04:05', '2024-01-02 03:04:05', 1, 'NOTE', 5),
+        (4, 4, '2024-01-02 03:04:05', '2024-01-02 03:04:05', 1, 'CASE', 5),
+        (5, 1, '2024-01-02 03:04:05', '2024-01-02 03:04:05', 1, 'TRASHED', 5),
+        (6, 2, '2024-01-02 03:04:05', '2024-01-02 03:04:05', 1, 'ORPHAN', 5),
+        (7, 1, '2024-01-02 03:04:05', '2024-01-02 03:04:05', 2, 'GROUP', 5);
+    INSERT INTO itemDataValues VALUES (1, 'an article'), (2, 'a journal'), (3, 'a case'), (4, 12);
+    INSERT INTO itemData VALUES (1, 1, 1), (1, 3, 2), (4, 2, 3), (1, 4, 4);
+    INSERT INTO creatorTypes VALUES (1, 'author');
+    INSERT INTO creators VALUES (1, 'Ada', 'Lovelace'), (2, 'Alan', 'Turing');
+    INSERT INTO itemCreators VALUES (1, 2, 1, 1), (1, 1, 1, 0);
+    INSERT INTO tags VALUES (1, 'math');
+    INSERT INTO itemTags VALUES (1, 1, 0);
+    INSERT INTO collections VALUES (1, 'root', NULL, 1, 'ROOT', 1), (2, 'child', 1, 1, 'CHILD', 1),
+        (3, 'trash', NULL, 1, 'TRASH', 1);
+    INSERT INTO collectionItems VALUES (2, 1, 0);
+    INSERT INTO itemAttachments VALUES (2, 1, 0, 'application/pdf', 'storage:paper.pdf', 1000, 'abc'),
+        (6, 5, 0, 'application/pdf', 'storage:gone.pdf', NULL, NULL);
+    INSERT INTO itemNotes VALUES (3, 1, '<p>note</p>', 'note');
+    INSERT INTO relationPredicates VALUES (1, 'dc:relation');
+    INSERT INTO itemRelations VALUES (1, 1, 'http://zotero.org/users/local/abc/items/CASE');
+    INSERT INTO deletedItems VALUES (5);
+    INSERT INTO deletedCollections VALUES (3);
+    ";
+
+    #[test]
+    fn test_load() {
+        let dir = std::env::temp_dir().join(format!("desktop-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let database = dir.join("zotero.sqlite");
+        std::fs::remove_file(&database).ok();
+        Connection::open(&database)
+            .unwrap()
+            .execute_batch(SCHEMA)
+            .unwrap();
+
+        let desktop = Desktop::snapshot(&database, &dir.join("snapshot.sqlite")).unwrap();
+        assert_eq!(desktop.user_name().as_deref(), Some("alice"));
+        let (collections, items) = desktop.load().unwrap();
+        std::fs::remove_dir_all(&dir).ok();
+
+        let mut keys: Vec<_> = collections.iter().map(|x| x.key.as_str()).collect();
+        keys.sort();
+        assert_eq!(keys, ["CHILD", "ROOT"]);
+        let child = collections.iter().find(|x| x.key == "CHILD").unwrap();
+        assert_eq!(child.data.parent_collection.as_deref(), Some("ROOT"));
+
+        let mut keys: Vec<_> = items.iter().map(|x| x.key.as_str()).collect();
+        keys.sort();
+        assert_eq!(keys, ["ARTICLE", "CASE", "NOTE", "PDF"]);
+        let get = |key: &str| items.iter().find(|x| x.key == key).unwrap();
+
+        let article = get("ARTICLE");
+        assert_eq!(article.data.title.as_deref(), Some("an article"));
+        assert_eq!(article.data.date_added, "2024-01-02T03:04:05Z");
+        assert_eq!(article.data.creators[0].last_name, "Lovelace");
+        assert_eq!(article.data.creators[1].last_name, "Turing");
+        assert_eq!(article.data.tags[0].tag, "math");
+        assert_eq!(article.data.collections, Some(vec!["CHILD".to_string()]));
+        assert_eq!(article.related_keys().collect::<Vec<_>>(), ["CASE"]);
+        assert_eq!(
+            article.data.extra_fields.get("publicationTitle"),
+            Some(&Value::String("a journal".to_string()))
+        );
+        assert_eq!(
+            article.data.extra_fields.get("volume"),
+            Some(&Value::String("12".to_string()))
+        );
+
+        let pdf = get("PDF");
+        assert_eq!(pdf.data.parent_item.as_deref(), Some("ARTICLE"));
+        assert_eq!(pdf.data.filename.as_deref(), Some("paper.pdf"));
+        assert_eq!(pdf.data.link_mode, Some(LinkMode::ImportedFile));
+        assert_eq!(pdf.data.collections, None);
+        assert!(pdf.is_stored_file());
+
+        assert_eq!(get("NOTE").data.parent_item.as_deref(), Some("ARTICLE"));
+        assert_eq!(get("CASE").data.title.as_deref(), Some("a case"));
+        assert_eq!(get("CASE").data.collections, Some(vec![]));
+    }
+}
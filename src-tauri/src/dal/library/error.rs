@@ -0,0 +1,11 @@
+#[derive(Debug, thiserror::Error)]
+pub enum LibraryError {
+    #[error("database error: {0}")]
+    Db(#[from] rusqlite::Error),
+
+    #[error("stored data error: {0}")]
+    Data(#[from] serde_json::Error),
+
+    #[error("io: {0}")]
+    Io(#[from] std::io::Error),
+}
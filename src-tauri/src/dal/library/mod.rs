@@ -0,0 +1,219 @@
+use std::path::Path;
+
+use error::LibraryError;
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::dal::zotero::api::collection::model::Collection;
+use crate::dal::zotero::api::item::model::Item;
+
+pub mod desktop;
+pub mod error;
+pub mod search;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS meta (name TEXT PRIMARY KEY, value INTEGER NOT NULL);
+CREATE TABLE IF NOT EXISTS collections (key TEXT PRIMARY KEY, json TEXT NOT NULL);
+CREATE TABLE IF NOT EXISTS items (key TEXT PRIMARY KEY, json TEXT NOT NULL);
+";
+
+/// changes of the remote library since the stored version
+#[derive(Debug, Default)]
+pub struct Changes {
+    pub user_id: i64,
+    pub version: i64,
+    /// the whole library rather than changes, the stored data is replaced
+    pub full: bool,
+    pub collections: Vec<Collection>,
+    pub items: Vec<Item>,
+    pub deleted_collections: Vec<String>,
+    pub deleted_items: Vec<String>,
+}
+
+/// local copy of the zotero library, items and collections are kept as the json
+/// returned by the api
+pub struct Library {
+    conn: Connection,
+}
+
+impl Library {
+    pub fn open(path: &Path) -> Result<Self, LibraryError> {
+        let conn = Connection::open(path)?;
+        conn.execute_batch(SCHEMA)?;
+        Ok(Self { conn })
+    }
+
+    fn meta(&self, name: &str) -> Result<Option<i64>, LibraryError> {
+        Ok(self
+            .conn
+            .query_row("SELECT value FROM meta WHERE name = ?1", [name], |row| {
+                row.get(0)
+            })
+            .optional()?)
+    }
+
+    /// library version of the stored data, 0 when nothing is stored
+    pub fn version(&self) -> Result<i64, LibraryError> {
+        Ok(self.meta("version")?.unwrap_or_default())
+    }
+
+    /// version to request changes since, stored data of another user is not reused
+    pub fn version_for(&self, user_id: i64) -> Result<i64, LibraryError> {
+        match self.meta("user_id")? {
+            Some(x) if x == user_id => self.version(),
+            _ => Ok(0),
+        }
+    }
+
+    pub fn load(&self) -> Result<(Vec<Collection>, Vec<Item>), LibraryError> {
+        let collections = self.load_table("SELECT json FROM collections")?;
+        let items = self.load_table("SELECT json FROM items")?;
+        Ok((collections, items))
+    }
+
+    fn load_table<T: serde::de::DeserializeOwned>(
+        &self,
+        sql: &str,
+    ) -> Result<Vec<T>, LibraryError> {
+        let mut stmt = self.conn.prepare(sql)?;
+        let rows = stmt.query_map([], |row| row.get::<_, String>(0))?;
+        let mut result = vec![];
+        for row in rows {
+            result.push(serde_json::from_str(&row?)?);
+        }
+        Ok(result)
+    }
+
+    /// store `changes` in one transaction. when they belong to another user, or are
+    /// the whole library, the old data is dropped first. items in the trash are
+    /// removed, like the desktop database does.
+    pub fn apply(&mut self, changes: &Changes) -> Result<(), LibraryError> {
+        let full = changes.full || self.version_for(changes.user_id)? == 0;
+        let tx = self.conn.transaction()?;
+        if full {
+            tx.execute("DELETE FROM collections", [])?;
+            tx.execute("DELETE FROM items", [])?;
+        }
+        {
+            let mut upsert = tx.prepare(
+                "INSERT INTO collections (key, json) VALUES (?1, ?2)
+                 ON CONFLICT(key) DO UPDATE SET json = excluded.json",
+            )?;
+            for collection in &changes.collections {
+                upsert.execute(params![collection.key, serde_json::to_string(collection)?])?;
+            }
+            let mut upsert = tx.prepare(
+                "INSERT INTO items (key, json) VALUES (?1, ?2)
+                 ON CONFLICT(key) DO UPDATE SET json = excluded.json",
+            )?;
+            let mut delete_item = tx.prepare("DELETE FROM items WHERE key = ?1")?;
+            for item in &changes.items {
+                if item.is_trashed() {
+                    delete_item.execute([&item.key])?;
+                } else {
+                    upsert.execute(params![item.key, serde_json::to_string(item)?])?;
+                }
+            }
+            for key in &changes.deleted_items {
+                delete_item.execute([key])?;
+            }
+            let mut delete = tx.prepare("DELETE FROM collections WHERE key = ?1")?;
+            for key in &changes.deleted_collections {
+                delete.execute([key])?;
+            }
+            let mut meta = tx.prepare(
+                "INSERT INTO meta (name, value) VALUES (?1, ?2)
+                 ON CONFLICT(name) DO UPDATE SET value = excluded.value",
+            )?;
+            meta.execute(params!["user_id", changes.user_id])?;
+            meta.execute(params!["version", changes.version])?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(key: &str, title: &str) -> Item {
+        let mut item = Item::default();
+        item.key = key.to_string();
+        item.data.title = Some(title.to_string());
+        item
+    }
+
+    #[test]
+    fn test_apply_changes() {
+        let mut library = Library::open(Path::new(":memory:")).unwrap();
+        assert_eq!(library.version().unwrap(), 0);
+
+        library
+            .apply(&Changes {
+                user_id: 1,
+                version: 10,
+                items: vec![item("A", "a"), item("B", "b")],
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(library.version_for(1).unwrap(), 10);
+        assert_eq!(library.version_for(2).unwrap(), 0);
+
+        library
+            .apply(&Changes {
+                user_id: 1,
+                version: 12,
+                items: vec![item("A", "a2")],
+                deleted_items: vec!["B".to_string()],
+                ..Default::default()
+            })
+            .unwrap();
+        let (_, items) = library.load().unwrap();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].data.title.as_deref(), Some("a2"));
+
+        library
+            .apply(&Changes {
+                user_id: 2,
+                version: 3,
+                items: vec![item("C", "c")],
+                ..Default::default()
+            })
+            .unwrap();
+        let (_, items) = library.load().unwrap();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].key, "C");
+
+        // a full fetch of the same user drops what it no longer contains
+        library
+            .apply(&Changes {
+                user_id: 2,
+                version: 3,
+                full: true,
+                items: vec![item("D", "d")],
+                ..Default::default()
+            })
+            .unwrap();
+        let (_, items) = library.load().unwrap();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].key, "D");
+
+        // moved to the trash
+        let mut trashed = item("D", "d");
+        trashed
+            .data
+            .extra_fields
+            .insert("deleted".to_string(), serde_json::json!(1));
+        library
+            .apply(&Changes {
+                user_id: 2,
+                version: 4,
+                items: vec![trashed, item("E", "e")],
+                ..Default::default()
+            })
+            .unwrap();
+        let (_, items) = library.load().unwrap();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].key, "E");
+    }
+}
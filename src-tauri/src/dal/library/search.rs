@@ -0,0 +1,446 @@
+use std::path::Path;
+use std::time::Duration;
+
+use ahash::AHashMap;
+use md5::{Digest, Md5};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+
+use super::error::LibraryError;
+
+/// `docs` maps item keys to the rows of the fts table, `texts` keeps the text of
+/// attachments so their items can be indexed again without reading the files
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS docs (id INTEGER PRIMARY KEY, key TEXT NOT NULL UNIQUE, signature INTEGER NOT NULL);
+CREATE TABLE IF NOT EXISTS texts (key TEXT PRIMARY KEY, parent TEXT NOT NULL, mtime INTEGER NOT NULL, text TEXT NOT NULL);
+CREATE INDEX IF NOT EXISTS texts_parent ON texts (parent);
+CREATE VIRTUAL TABLE IF NOT EXISTS items USING fts5(
+    title, creators, abstract, tags, notes, fulltext, year,
+    tokenize = 'unicode61 remove_diacritics 2', prefix = '2 3'
+);
+";
+
+/// weights of the columns of `items` in the ranking, a match in the title counts most
+const RANK: &str = "bm25(items, 10.0, 5.0, 2.0, 4.0, 1.0, 1.0, 1.0)";
+
+/// marks the start and end of a match in snippets, they never occur in text
+const HIGHLIGHT_START: char = '\u{1}';
+const HIGHLIGHT_END: char = '\u{2}';
+
+/// words of the snippet around the best match
+const SNIPPET_TOKENS: i64 = 16;
+
+/// what is searched of a top level item, its notes and attachment texts included
+#[derive(Debug, Default)]
+pub struct Document {
+    pub key: String,
+    pub title: String,
+    pub creators: String,
+    pub abstract_note: String,
+    pub tags: String,
+    pub notes: String,
+    pub year: String,
+    /// attachments whose text belongs to this item, the item itself when it is one
+    pub attachments: Vec<String>,
+}
+
+impl Document {
+    /// changes whenever the indexed content does. it is stored, so it has to stay
+    /// the same across builds.
+    fn signature(&self) -> i64 {
+        let mut h = Md5::new();
+        let fields = [
+            &self.key,
+            &self.title,
+            &self.creators,
+            &self.abstract_note,
+            &self.tags,
+            &self.notes,
+            &self.year,
+        ];
+        for field in fields.into_iter().chain(&self.attachments) {
+            h.update(field.as_bytes());
+            h.update([0]);
+        }
+        let digest = h.finalize();
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(&digest[..8]);
+        i64::from_le_bytes(bytes)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SnippetPart {
+    pub text: String,
+    pub highlight: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Hit {
+    pub key: String,
+    /// higher is better
+    pub score: f64,
+    pub title: Vec<SnippetPart>,
+    /// around the best match in any field
+    pub snippet: Vec<SnippetPart>,
+}
+
+/// full text index of a library, kept next to it and updated item by item
+pub struct SearchIndex {
+    conn: Connection,
+}
+
+impl SearchIndex {
+    pub fn open(path: &Path) -> Result<Self, LibraryError> {
+        let conn = Connection::open(path)?;
+        // a refresh and a finished download may update the index at the same time
+        conn.busy_timeout(Duration::from_secs(30))?;
+        conn.execute_batch(SCHEMA)?;
+        Ok(Self { conn })
+    }
+
+    /// index `documents` whose content changed and drop the items not among them,
+    /// returns how many were written
+    pub fn sync(&mut self, documents: &[Document]) -> Result<usize, LibraryError> {
+        let indexed: AHashMap<String, i64> = {
+            let mut stmt = self.conn.prepare("SELECT key, signature FROM docs")?;
+            let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+            rows.collect::<Result<_, _>>()?
+        };
+        let tx = self.conn.transaction()?;
+        let mut written = 0;
+        for document in documents {
+            let signature = document.signature();
+            if indexed.get(&document.key) == Some(&signature) {
+                continue;
+            }
+            write(&tx, document, signature)?;
+            written += 1;
+        }
+        let keys: ahash::AHashSet<&str> = documents.iter().map(|x| x.key.as_str()).collect();
+        for key in indexed.keys().filter(|x| !keys.contains(x.as_str())) {
+            remove(&tx, key)?;
+            tx.execute("DELETE FROM texts WHERE parent = ?1", [key])?;
+        }
+        tx.commit()?;
+        Ok(written)
+    }
+
+    /// modification time of the file the stored text of attachment `key` came from
+    pub fn text_mtime(&self, key: &str) -> Result<Option<i64>, LibraryError> {
+        Ok(self
+            .conn
+            .query_row("SELECT mtime FROM texts WHERE key = ?1", [key], |row| {
+                row.get(0)
+            })
+            .optional()?)
+    }
+
+    /// store the text of attachment `key` and index it with the item `parent`
+    pub fn set_text(
+        &mut self,
+        key: &str,
+        parent: &str,
+        mtime: i64,
+        text: &str,
+    ) -> Result<(), LibraryError> {
+        let tx = self.conn.transaction()?;
+        tx.execute(
+            "INSERT INTO texts (key, parent, mtime, text) VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT(key) DO UPDATE SET parent = excluded.parent,
+             mtime = excluded.mtime, text = excluded.text",
+            params![key, parent, mtime, text],
+        )?;
+        tx.execute(
+            "UPDATE items SET fulltext = (SELECT group_concat(text, ' ') FROM texts WHERE parent = ?1)
+             WHERE rowid = (SELECT id FROM docs WHERE key = ?1)",
+            [parent],
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// items matching `query`, an fts expression made by [`parse_query`], best first
+    pub fn search(&self, query: &str, limit: usize) -> Result<Vec<Hit>, LibraryError> {
+        let sql = format!(
+            "SELECT docs.key, {rank},
+                highlight(items, 0, ?2, ?3),
+                snippet(items, -1, ?2, ?3, '…', ?4)
+             FROM items JOIN docs ON docs.id = items.rowid
+             WHERE items MATCH ?1 ORDER BY {rank} LIMIT ?5",
+            rank = RANK
+        );
+        let mut stmt = self.conn.prepare(&sql)?;
+        let rows = stmt.query_map(
+            params![
+                query,
+                HIGHLIGHT_START.to_string(),
+                HIGHLIGHT_END.to_string(),
+                SNIPPET_TOKENS,
+                limit as i64
+            ],
+            |row| {
+                Ok(Hit {
+                    key: row.get(0)?,
+                    score: -row.get::<_, f64>(1)?,
+                    title: snippet_parts(&row.get::<_, String>(2)?),
+                    snippet: snippet_parts(&row.get::<_, String>(3)?),
+                })
+            },
+        )?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+}
+
+fn write(
+    tx: &rusqlite::Transaction,
+    document: &Document,
+    signature: i64,
+) -> Result<(), LibraryError> {
+    remove(tx, &document.key)?;
+    // texts of attachments that were deleted or moved to another item
+    let stale: Vec<String> = {
+        let mut stmt = tx.prepare("SELECT key FROM texts WHERE parent = ?1")?;
+        let rows = stmt.query_map([&document.key], |row| row.get::<_, String>(0))?;
+        rows.filter(|x| {
+            x.as_ref()
+                .map_or(true, |x| !document.attachments.contains(x))
+        })
+        .collect::<Result<_, _>>()?
+    };
+    for key in stale {
+        tx.execute("DELETE FROM texts WHERE key = ?1", [key])?;
+    }
+    tx.execute(
+        "INSERT INTO docs (key, signature) VALUES (?1, ?2)",
+        params![document.key, signature],
+    )?;
+    tx.execute(
+        "INSERT INTO items (rowid, title, creators, abstract, tags, notes, fulltext, year)
+         VALUES (last_insert_rowid(), ?1, ?2, ?3, ?4, ?5,
+            (SELECT group_concat(text, ' ') FROM texts WHERE parent = ?6), ?7)",
+        params![
+            document.title,
+            document.creators,
+            document.abstract_note,
+            document.tags,
+            document.notes,
+            document.key,
+            document.year
+        ],
+    )?;
+    Ok(())
+}
+
+fn remove(tx: &rusqlite::Transaction, key: &str) -> Result<(), LibraryError> {
+    tx.execute(
+        "DELETE FROM items WHERE rowid = (SELECT id FROM docs WHERE key = ?1)",
+        [key],
+    )?;
+    tx.execute("DELETE FROM docs WHERE key = ?1", [key])?;
+    Ok(())
+}
+
+fn snippet_parts(text: &str) -> Vec<SnippetPart> {
+    let mut parts = vec![];
+    let mut highlight = false;
+    for piece in text.split([HIGHLIGHT_START, HIGHLIGHT_END]) {
+        if !piece.is_empty() {
+            parts.push(SnippetPart {
+                text: piece.to_string(),
+                highlight,
+            });
+        }
+        highlight = !highlight;
+    }
+    parts
+}
+
+/// turn what the user typed into an fts expression, `None` when nothing is left
+/// to search for.
+///
+/// words match as prefixes, `"quoted words"` as a phrase. `author:`, `tag:`,
+/// `title:` and `year:` search in one field, `year:2010-2015` in a range of years.
+pub fn parse_query(input: &str) -> Option<String> {
+    let mut terms = vec![];
+    for token in tokenize(input) {
+        let (column, value) = match token.split_once(':') {
+            Some((field, value)) if !value.is_empty() => match field {
+                "author" | "creator" => (Some("creators"), value),
+                "tag" => (Some("tags"), value),
+                "title" => (Some("title"), value),
+                "year" => {
+                    if let Some(years) = year_range(value) {
+                        terms.push(format!("year : ({})", years));
+                        continue;
+                    }
+                    (Some("year"), value)
+                }
+                _ => (None, token.as_str()),
+            },
+            _ => (None, token.as_str()),
+        };
+        let phrase = value.starts_with('"');
+        let value = value.trim_matches('"');
+        if !value.chars().any(|x| x.is_alphanumeric()) {
+            continue;
+        }
+        let mut term = format!("\"{}\"", value.replace('"', "\"\""));
+        if !phrase {
+            term.push('*');
+        }
+        terms.push(match column {
+            Some(column) => format!("{} : {}", column, term),
+            None => term,
+        });
+    }
+    (!terms.is_empty()).then(|| terms.join(" "))
+}
+
+/// split on whitespace outside of double quotes
+fn tokenize(input: &str) -> Vec<String> {
+    let mut tokens = vec![];
+    let mut token = String::new();
+    let mut quoted = false;
+    for x in input.chars() {
+        match x {
+            '"' => {
+                quoted = !quoted;
+                token.push(x);
+            }
+            x if x.is_whitespace() && !quoted => {
+                if !token.is_empty() {
+                    tokens.push(std::mem::take(&mut token));
+                }
+            }
+            x => token.push(x),
+        }
+    }
+    if !token.is_empty() {
+        tokens.push(token);
+    }
+    tokens
+}
+
+/// `2010-2015` as `"2010" OR … OR "2015"`
+fn year_range(value: &str) -> Option<String> {
+    let (from, to) = value.split_once('-')?;
+    let (from, to): (i32, i32) = (from.parse().ok()?, to.parse().ok()?);
+    if from > to || to - from > 200 {
+        return None;
+    }
+    Some(
+        (from..=to)
+            .map(|x| format!("\"{}\"", x))
+            .collect::<Vec<_>>()
+            .join(" OR "),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn document(key: &str, title: &str, creators: &str, year: &str) -> Document {
+        Document {
+            key: key.to_string(),
+            title: title.to_string(),
+            creators: creators.to_string(),
+            year: year.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn search(index: &SearchIndex, query: &str) -> Vec<String> {
+        index
+            .search(&parse_query(query).unwrap(), 10)
+            .unwrap()
+            .into_iter()
+            .map(|x| x.key)
+            .collect()
+    }
+
+    #[test]
+    fn test_signature() {
+        let a = document("A", "deep nets", "Smith", "2020");
+        // stored in the index, a new build must not change it
+        assert_eq!(a.signature(), 4893370291392518290);
+        assert_ne!(
+            a.signature(),
+            document("A", "deep net", "Smith", "2020").signature()
+        );
+        // fields do not run into each other
+        assert_ne!(
+            a.signature(),
+            document("A", "deep net", "sSmith", "2020").signature()
+        );
+    }
+
+    #[test]
+    fn test_parse_query() {
+        assert_eq!(parse_query("  "), None);
+        assert_eq!(
+            parse_query("deep lea"),
+            Some(r#""deep"* "lea"*"#.to_string())
+        );
+        assert_eq!(
+            parse_query(r#""neural net" author:Hinton tag:ml"#),
+            Some(r#""neural net" creators : "Hinton"* tags : "ml"*"#.to_string())
+        );
+        assert_eq!(
+            parse_query("year:2019-2020 year:2021 a\"b"),
+            Some(r#"year : ("2019" OR "2020") year : "2021"* "a""b"*"#.to_string())
+        );
+        assert_eq!(parse_query("foo:bar"), Some(r#""foo:bar"*"#.to_string()));
+    }
+
+    #[test]
+    fn test_search() {
+        let mut index = SearchIndex::open(Path::new(":memory:")).unwrap();
+        let documents = vec![
+            document("A", "Deep learning", "Yann LeCun; Geoffrey Hinton", "2015"),
+            document("B", "Learning to rank", "Tie-Yan Liu", "2009"),
+            document("C", "Graph theory", "Frank Harary", "1969"),
+        ];
+        assert_eq!(index.sync(&documents).unwrap(), 3);
+        assert_eq!(index.sync(&documents).unwrap(), 0);
+
+        assert_eq!(search(&index, "learn"), ["A", "B"]);
+        assert_eq!(search(&index, "learn author:hint"), ["A"]);
+        assert_eq!(search(&index, "year:2000-2010"), ["B"]);
+
+        let hits = index.search(&parse_query("graph").unwrap(), 10).unwrap();
+        assert_eq!(
+            hits[0].title,
+            [
+                SnippetPart {
+                    text: "Graph".to_string(),
+                    highlight: true
+                },
+                SnippetPart {
+                    text: " theory".to_string(),
+                    highlight: false
+                }
+            ]
+        );
+
+        index
+            .set_text("PDF", "C", 1, "a walk through königsberg")
+            .unwrap();
+        assert_eq!(search(&index, "konigsberg"), ["C"]);
+        // the text stays when the item is indexed again
+        let mut changed = documents;
+        changed[2].title = "Graph theory, 2nd edition".to_string();
+        changed[2].attachments = vec!["PDF".to_string()];
+        changed.remove(1);
+        assert_eq!(index.sync(&changed).unwrap(), 1);
+        assert_eq!(search(&index, "konigsberg"), ["C"]);
+        assert_eq!(search(&index, "rank"), Vec::<String>::new());
+        assert_eq!(index.text_mtime("PDF").unwrap(), Some(1));
+        // and goes with its attachment
+        changed[1].attachments.clear();
+        assert_eq!(index.sync(&changed).unwrap(), 1);
+        assert_eq!(search(&index, "konigsberg"), Vec::<String>::new());
+        assert_eq!(index.text_mtime("PDF").unwrap(), None);
+    }
+}
@@ -1,2 +1,3 @@
+pub mod library;
 pub mod webdav;
 pub mod zotero;
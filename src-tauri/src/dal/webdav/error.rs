@@ -5,4 +5,8 @@ pub enum WebDavError {
 
     #[error("response error: {0}")]
     Response(#[from] reqwest::Error),
+
+    /// the account has no server, username or password for webdav
+    #[error("webdav is not configured")]
+    NotConfigured,
 }
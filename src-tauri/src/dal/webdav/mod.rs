@@ -1,9 +1,10 @@
-use std::sync::LazyLock;
+use std::sync::{Arc, LazyLock};
 
 use ahash::AHashMap;
 use error::WebDavError;
 use parking_lot::RwLock;
-use reqwest::{Body, Response};
+use reqwest::header::HeaderMap;
+use reqwest::{Body, Method, Response};
 use reqwest_dav::{Auth, Client, ClientBuilder};
 use tracing::debug;
 
@@ -21,35 +22,56 @@ pub struct WebDavClient {
 }
 
 impl WebDavClient {
-    pub async fn get(&self, path: impl AsRef<str>) -> Result<Response, WebDavError> {
-        debug!("get: {}", path.as_ref());
-        Ok(self.client.get(path.as_ref()).await?)
+    pub async fn get(
+        &self,
+        path: impl AsRef<str>,
+        headers: HeaderMap,
+    ) -> Result<Response, WebDavError> {
+        debug!("get: {}, headers: {:?}", path.as_ref(), headers);
+        Ok(self
+            .client
+            .start_request(Method::GET, path.as_ref())
+            .await?
+            .headers(headers)
+            .send()
+            .await?
+            .error_for_status()?)
     }
 
     pub async fn put(
         &self,
         path: impl AsRef<str>,
         body: impl Into<Body>,
+        headers: HeaderMap,
     ) -> Result<(), WebDavError> {
-        debug!("put: {}", path.as_ref());
-        Ok(self.client.put(path.as_ref(), body).await?)
+        debug!("put: {}, headers: {:?}", path.as_ref(), headers);
+        self.client
+            .start_request(Method::PUT, path.as_ref())
+            .await?
+            .headers(headers)
+            .body(body)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
     }
 }
 
+static CLIENTS: LazyLock<RwLock<AHashMap<String, Arc<WebDavClient>>>> =
+    LazyLock::new(|| RwLock::new(AHashMap::new()));
+
+/// clients are cached by host and credentials
 pub fn client(
     host: impl AsRef<str>,
     auth: Option<WebDavAuth>,
-) -> Result<&'static WebDavClient, WebDavError> {
-    static CLIENTS: LazyLock<RwLock<AHashMap<String, &'static WebDavClient>>> =
-        LazyLock::new(|| RwLock::new(AHashMap::new()));
-
+) -> Result<Arc<WebDavClient>, WebDavError> {
     let mut key = host.as_ref().to_string();
     if let Some(auth) = auth.as_ref() {
         key += &format!(":{}:{}", auth.username, auth.password);
     }
 
     if let Some(c) = CLIENTS.read().get(&key) {
-        return Ok(*c);
+        return Ok(c.clone());
     }
 
     let mut client_builder = ClientBuilder::new().set_host(host.as_ref().to_string());
@@ -59,11 +81,15 @@ pub fn client(
     }
 
     let client = client_builder.build()?;
-    let client = WebDavClient { client };
-    let client_ref = Box::leak(Box::new(client));
+    let client = Arc::new(WebDavClient { client });
+
+    CLIENTS.write().insert(key, client.clone());
+    Ok(client)
+}
 
-    CLIENTS.write().insert(key, client_ref);
-    Ok(client_ref)
+/// drop every cached client together with the credentials it holds
+pub fn clear_clients() {
+    CLIENTS.write().clear();
 }
 
 #[cfg(test)]
@@ -77,7 +103,7 @@ mod tests {
     async fn test_no_auth_client() {
         let client = client(dotenv!("WEB_DAV_HELLO_HOST"), None).unwrap();
 
-        let resp = client.get("/").await.unwrap();
+        let resp = client.get("/", HeaderMap::new()).await.unwrap();
         assert!(resp.status().is_success());
 
         let text = resp.text().await;
@@ -97,7 +123,10 @@ mod tests {
         )
         .unwrap();
 
-        let resp = client.get(dotenv!("WEB_DAV_DATA_PATH")).await.unwrap();
+        let resp = client
+            .get(dotenv!("WEB_DAV_DATA_PATH"), HeaderMap::new())
+            .await
+            .unwrap();
         assert!(resp.status().is_success());
 
         let text = resp.text().await;
@@ -117,7 +146,7 @@ mod tests {
         )
         .unwrap();
 
-        let resp = client.get("/").await.unwrap();
+        let resp = client.get("/", HeaderMap::new()).await.unwrap();
         assert!(resp.status().is_success());
 
         let text = resp.text().await;
@@ -1,18 +1,28 @@
 use model::Collection;
 use tracing::debug;
 
-use crate::dal::zotero::{error::ZoteroError, Zotero};
+use crate::dal::zotero::{error::ZoteroError, model::Versioned, Zotero};
 
 pub mod model;
 
 impl Zotero {
     // TODO: Returns up to 100 collections per page, Add pagination support for large result sets
+    #[allow(dead_code)]
     pub async fn get_all_collections(&self) -> Result<Vec<Collection>, ZoteroError> {
         let resp = self.user_get("/collections").await?;
         debug!("get collections: {}", serde_json::to_string(&resp).unwrap());
         Ok(resp)
     }
 
+    /// collections changed after `version`, 0 returns every collection. all pages are read.
+    pub async fn get_collections_since(
+        &self,
+        version: i64,
+    ) -> Result<Versioned<Vec<Collection>>, ZoteroError> {
+        self.user_get_all(format!("/collections?since={}", version))
+            .await
+    }
+
     #[allow(dead_code)]
     pub async fn get_collection_top(&self) -> Result<Vec<Collection>, ZoteroError> {
         let resp = self.user_get("/collections/top").await?;
@@ -28,7 +38,9 @@ mod tests {
     #[cfg(feature = "__local_test__")]
     #[tokio::test]
     async fn test_get_collections() {
-        let zotero = Zotero::new(dotenv!("ZOTERO_API_KEY").into()).await.unwrap();
+        let zotero = Zotero::new(Default::default(), dotenv!("ZOTERO_API_KEY").into())
+            .await
+            .unwrap();
         let collections = zotero.get_all_collections().await.unwrap();
         println!("{:?}", collections);
     }
@@ -36,7 +48,9 @@ mod tests {
     #[cfg(feature = "__local_test__")]
     #[tokio::test]
     async fn test_get_collection_top() {
-        let zotero = Zotero::new(dotenv!("ZOTERO_API_KEY").into()).await.unwrap();
+        let zotero = Zotero::new(Default::default(), dotenv!("ZOTERO_API_KEY").into())
+            .await
+            .unwrap();
         let collection = zotero.get_collection_top().await.unwrap();
         println!("{}", serde_json::to_string(&collection).unwrap());
     }
@@ -1,16 +1,24 @@
 use model::{Item, UploadAuthOk, UploadAuthResponse};
 
-use crate::dal::zotero::{error::ZoteroError, Zotero};
+use crate::dal::zotero::{error::ZoteroError, model::Versioned, Zotero};
 
 pub mod model;
 
 impl Zotero {
     // TODO: Returns up to 100 items per page, Add pagination support for large result sets
+    #[allow(dead_code)]
     pub async fn get_all_items(&self) -> Result<Vec<Item>, ZoteroError> {
         let resp = self.user_get("/items").await?;
         Ok(resp)
     }
 
+    /// items changed after `version`, 0 returns every item. all pages are read.
+    /// trashed items are included, so items moved to the trash can be dropped.
+    pub async fn get_items_since(&self, version: i64) -> Result<Versioned<Vec<Item>>, ZoteroError> {
+        self.user_get_all(format!("/items?since={}&includeTrashed=1", version))
+            .await
+    }
+
     #[allow(dead_code)]
     pub async fn get_collection_top_items(
         &self,
@@ -56,7 +64,9 @@ mod tests {
     #[cfg(feature = "__local_test__")]
     #[tokio::test]
     async fn test_get_items() {
-        let zotero = Zotero::new(dotenv!("ZOTERO_API_KEY").into()).await.unwrap();
+        let zotero = Zotero::new(Default::default(), dotenv!("ZOTERO_API_KEY").into())
+            .await
+            .unwrap();
         let items = zotero.get_all_items().await.unwrap();
         println!("{}", serde_json::to_string(&items).unwrap());
     }
@@ -64,7 +74,9 @@ mod tests {
     #[cfg(feature = "__local_test__")]
     #[tokio::test]
     async fn test_get_collection_items() {
-        let zotero = Zotero::new(dotenv!("ZOTERO_API_KEY").into()).await.unwrap();
+        let zotero = Zotero::new(Default::default(), dotenv!("ZOTERO_API_KEY").into())
+            .await
+            .unwrap();
         let items = zotero
             .get_collection_top_items(dotenv!("ZOTERO_COLLECTION_KEY"))
             .await
@@ -75,7 +87,9 @@ mod tests {
     #[cfg(feature = "__local_test__")]
     #[tokio::test]
     async fn test_get_upload_auth() {
-        let zotero = Zotero::new(dotenv!("ZOTERO_API_KEY").into()).await.unwrap();
+        let zotero = Zotero::new(Default::default(), dotenv!("ZOTERO_API_KEY").into())
+            .await
+            .unwrap();
         let form = [
             ("md5", dotenv!("TEST_ITEM_NEW_MD5")),
             ("filename", "test"),
@@ -1,6 +1,9 @@
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 use std::collections::HashMap;
 
+/// predicate of the "related" links between items
+pub const RELATED_PREDICATE: &str = "dc:relation";
+
 #[derive(Debug, Default, Serialize, Deserialize)]
 #[serde(default)]
 #[serde(rename_all = "camelCase")]
@@ -17,6 +20,101 @@ pub struct Item {
     pub sub_items: Vec<Item>,
 }
 
+impl Item {
+    pub fn is_attachment(&self) -> bool {
+        self.data.item_type == "attachment"
+    }
+
+    pub fn attachments(&self) -> impl Iterator<Item = &Item> {
+        self.sub_items.iter().filter(|x| x.is_attachment())
+    }
+
+    pub fn is_note(&self) -> bool {
+        self.data.item_type == "note"
+    }
+
+    pub fn notes(&self) -> impl Iterator<Item = &Item> {
+        self.sub_items.iter().filter(|x| x.is_note())
+    }
+
+    /// html of a note item
+    pub fn note(&self) -> Option<&str> {
+        self.data.extra_fields.get("note").and_then(|x| x.as_str())
+    }
+
+    /// moved to the trash, the api sends `1` or `true`
+    pub fn is_trashed(&self) -> bool {
+        self.data
+            .extra_fields
+            .get("deleted")
+            .is_some_and(|x| x.as_bool() == Some(true) || x.as_i64() == Some(1))
+    }
+
+    /// `Smith`, `Smith and Jones` or `Smith et al.`, from the api when it sent one.
+    /// authors are preferred over the other creator types, like zotero does.
+    pub fn creator_summary(&self) -> String {
+        if let Some(summary) = &self.meta.creator_summary {
+            return summary.clone();
+        }
+        let creators = &self.data.creators;
+        let authors: Vec<&Creator> = creators
+            .iter()
+            .filter(|x| x.creator_type == "author")
+            .collect();
+        let names: Vec<&str> = if authors.is_empty() {
+            creators.iter().map(|x| x.last_name.as_str()).collect()
+        } else {
+            authors.iter().map(|x| x.last_name.as_str()).collect()
+        };
+        match names.as_slice() {
+            [] => String::new(),
+            [a] => a.to_string(),
+            [a, b] => format!("{} and {}", a, b),
+            [a, ..] => format!("{} et al.", a),
+        }
+    }
+
+    /// year of the item's date, the api parses it into `meta.parsed_date`, the
+    /// desktop database keeps it at the start of the `date` field
+    pub fn year(&self) -> Option<i32> {
+        let date = self
+            .meta
+            .parsed_date
+            .as_deref()
+            .or_else(|| self.data.extra_fields.get("date").and_then(|x| x.as_str()))?;
+        let start = date.find(|x: char| x.is_ascii_digit())?;
+        let digits = &date[start..];
+        let end = digits
+            .find(|x: char| !x.is_ascii_digit())
+            .unwrap_or(digits.len());
+        match digits[..end].parse() {
+            Ok(year) if end == 4 && year > 0 => Some(year),
+            _ => None,
+        }
+    }
+
+    /// keys of the items linked as related, the relations name them by uri
+    /// such as `http://zotero.org/users/1/items/ABCD2345`
+    pub fn related_keys(&self) -> impl Iterator<Item = &str> {
+        self.data
+            .relations
+            .get(RELATED_PREDICATE)
+            .into_iter()
+            .flatten()
+            .filter_map(|x| x.rsplit_once("/items/").map(|x| x.1))
+    }
+
+    /// attachment whose file is synced through file storage
+    pub fn is_stored_file(&self) -> bool {
+        self.is_attachment()
+            && self.data.filename.is_some()
+            && !matches!(
+                self.data.link_mode,
+                Some(LinkMode::LinkedFile | LinkMode::LinkedUrl)
+            )
+    }
+}
+
 #[derive(Debug, Default, Serialize, Deserialize)]
 #[serde(default)]
 #[serde(rename_all = "camelCase")]
@@ -95,16 +193,59 @@ pub struct ItemData {
     pub tags: Vec<Tag>,
     #[serde(default)]
     pub collections: Option<Vec<String>>,
-    pub relations: HashMap<String, String>,
+    /// predicate to object uris, the api sends a single uri as a plain string
+    #[serde(deserialize_with = "one_or_many")]
+    pub relations: HashMap<String, Vec<String>>,
     pub date_added: String,
     pub date_modified: String,
     pub content_type: String,
     pub filename: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub link_mode: Option<LinkMode>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub md5: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mtime: Option<i64>,
+    /// only set for `linked_file` attachments, relative paths start with `attachments:`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
     // 其他可选字段...
     #[serde(flatten)]
     pub extra_fields: HashMap<String, serde_json::Value>,
 }
 
+fn one_or_many<'de, D>(deserializer: D) -> Result<HashMap<String, Vec<String>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+    let map: HashMap<String, OneOrMany> = HashMap::deserialize(deserializer)?;
+    Ok(map
+        .into_iter()
+        .map(|(k, v)| match v {
+            OneOrMany::One(x) => (k, vec![x]),
+            OneOrMany::Many(x) => (k, x),
+        })
+        .collect())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LinkMode {
+    ImportedFile,
+    ImportedUrl,
+    LinkedFile,
+    LinkedUrl,
+    EmbeddedImage,
+    #[serde(other)]
+    Unknown,
+}
+
 #[derive(Debug, Default, Serialize, Deserialize)]
 #[serde(default)]
 #[serde(rename_all = "camelCase")]
@@ -145,3 +286,25 @@ pub struct UploadAuthOk {
 pub struct UploadAuthExist {
     pub exists: String,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_relations() {
+        let data: ItemData = serde_json::from_str(
+            r#"{"relations": {
+                "dc:relation": "http://zotero.org/users/1/items/AAAA2222",
+                "owl:sameAs": ["http://zotero.org/groups/2/items/BBBB3333"]
+            }}"#,
+        )
+        .unwrap();
+        let item = Item {
+            data,
+            ..Default::default()
+        };
+        assert_eq!(item.related_keys().collect::<Vec<_>>(), ["AAAA2222"]);
+        assert_eq!(item.data.relations["owl:sameAs"].len(), 1);
+    }
+}
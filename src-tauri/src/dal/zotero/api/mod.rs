@@ -1,2 +1,3 @@
 pub mod collection;
 pub mod item;
+pub mod schema;
@@ -0,0 +1,124 @@
+use model::{CreatorTypeLabel, FieldLabel, ItemTypeLabel};
+
+use crate::dal::zotero::{error::ZoteroError, Zotero};
+
+pub mod model;
+
+/// fields of the common item types in the order zotero shows them, used when
+/// the schema can not be fetched
+pub const FIELD_ORDER: &[&str] = &[
+    "title",
+    "abstractNote",
+    "publicationTitle",
+    "bookTitle",
+    "proceedingsTitle",
+    "websiteTitle",
+    "blogTitle",
+    "university",
+    "thesisType",
+    "reportType",
+    "institution",
+    "volume",
+    "issue",
+    "pages",
+    "numPages",
+    "edition",
+    "series",
+    "seriesTitle",
+    "seriesNumber",
+    "numberOfVolumes",
+    "place",
+    "publisher",
+    "date",
+    "journalAbbreviation",
+    "conferenceName",
+    "language",
+    "DOI",
+    "ISBN",
+    "ISSN",
+    "shortTitle",
+    "url",
+    "accessDate",
+    "archive",
+    "archiveLocation",
+    "libraryCatalog",
+    "callNumber",
+    "rights",
+    "extra",
+];
+
+impl Zotero {
+    /// item types with their names in `locale`, such as `de-DE`
+    pub async fn item_types(&self, locale: &str) -> Result<Vec<ItemTypeLabel>, ZoteroError> {
+        self.schema_get(format!("/itemTypes?locale={}", locale))
+            .await
+    }
+
+    /// fields of `item_type` in display order
+    pub async fn item_type_fields(
+        &self,
+        item_type: &str,
+        locale: &str,
+    ) -> Result<Vec<FieldLabel>, ZoteroError> {
+        self.schema_get(format!(
+            "/itemTypeFields?itemType={}&locale={}",
+            item_type, locale
+        ))
+        .await
+    }
+
+    /// creator types valid for `item_type`, the primary one first
+    pub async fn item_type_creator_types(
+        &self,
+        item_type: &str,
+        locale: &str,
+    ) -> Result<Vec<CreatorTypeLabel>, ZoteroError> {
+        self.schema_get(format!(
+            "/itemTypeCreatorTypes?itemType={}&locale={}",
+            item_type, locale
+        ))
+        .await
+    }
+}
+
+/// english label of a field or creator type, `publicationTitle` becomes
+/// `Publication title`
+pub fn fallback_label(name: &str) -> String {
+    match name {
+        "abstractNote" => return "Abstract".to_string(),
+        "url" => return "URL".to_string(),
+        "accessDate" => return "Accessed".to_string(),
+        "numPages" => return "# of Pages".to_string(),
+        "dateAdded" => return "Date Added".to_string(),
+        "dateModified" => return "Modified".to_string(),
+        _ => {}
+    }
+    if name.chars().all(|x| !x.is_lowercase()) {
+        return name.to_string();
+    }
+    let mut label = String::with_capacity(name.len() + 4);
+    for (i, c) in name.chars().enumerate() {
+        if i == 0 {
+            label.extend(c.to_uppercase());
+        } else if c.is_uppercase() {
+            label.push(' ');
+            label.extend(c.to_lowercase());
+        } else {
+            label.push(c);
+        }
+    }
+    label
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fallback_label() {
+        assert_eq!(fallback_label("publicationTitle"), "Publication title");
+        assert_eq!(fallback_label("DOI"), "DOI");
+        assert_eq!(fallback_label("url"), "URL");
+        assert_eq!(fallback_label("author"), "Author");
+    }
+}
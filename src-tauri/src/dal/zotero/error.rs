@@ -10,10 +10,35 @@ pub enum ZoteroError {
     ApiKey(String),
     #[error("request invalid: http code: {0}, message: {0}.canonical_reason()")]
     RequestInvalid(StatusCode),
+    #[error("api key has no access to the library")]
+    InsufficientAccess,
+    #[error("unavailable: {0}")]
+    Unavailable(String),
+    #[error("oauth error: {0}")]
+    OAuth(String),
+    #[error("stream error: {0}")]
+    Stream(String),
     #[error("not login")]
     NotLogin,
     #[error("no data, please login first and refresh")]
     NoData,
     #[error("no pdf found")]
     NoPdf,
+    #[error("attachment not found: {0}")]
+    NoAttachment(String),
+    #[error("linked file uses a relative path but no base directory is set")]
+    NoBaseDirectory,
+    #[error("linked file not found: {0}")]
+    LinkedFileMissing(String),
+}
+
+impl ZoteroError {
+    /// the key was deleted or lost its permissions, as opposed to a network failure
+    pub fn is_revoked(&self) -> bool {
+        matches!(
+            self,
+            Self::RequestInvalid(StatusCode::FORBIDDEN | StatusCode::NOT_FOUND)
+                | Self::InsufficientAccess
+        )
+    }
 }
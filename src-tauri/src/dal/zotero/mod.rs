@@ -1,46 +1,148 @@
-use std::sync::{Arc, LazyLock};
+use std::sync::Arc;
 
 use api::item::model::UploadAuthOk;
 use error::ZoteroError;
-use model::{KeyResp, ZoteroApiKey};
-use reqwest::{Client, ClientBuilder, Response};
+use model::{Access, Capabilities, Deleted, KeyInfo, KeyResp, Versioned, ZoteroApiKey};
+use reqwest::{Client, ClientBuilder, RequestBuilder, Response};
+use serde::{Deserialize, Serialize};
 use tauri::http::HeaderMap;
 pub mod api;
 pub mod error;
 pub mod model;
+pub mod oauth;
+pub mod stream;
 use tracing::error;
 
 use crate::model::auth::UserName;
 
-const BASE_URL: &str = "https://api.zotero.org";
+pub const API_URL: &str = "https://api.zotero.org";
+pub const STREAM_URL: &str = "wss://stream.zotero.org";
+/// read-only api of a running zotero 7 desktop, it has to be enabled in its settings
+pub const LOCAL_API_URL: &str = "http://localhost:23119/api";
+/// the local api serves the desktop user's library as user 0
+pub const LOCAL_USER_ID: i64 = 0;
+/// objects per request, the most the api allows
+const PAGE_SIZE: usize = 100;
 
-fn client() -> &'static Client {
-    static CLIENT: LazyLock<Client> = LazyLock::new(|| {
-        let mut client_builder = ClientBuilder::new();
+/// where the api of an account is served, zotero.org by default. a self-hosted
+/// dataserver, a proxy or a local stand-in in tests can be used instead.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Endpoint {
+    pub api_url: String,
+    pub stream_url: String,
+}
+
+impl Default for Endpoint {
+    fn default() -> Self {
+        Self {
+            api_url: API_URL.to_string(),
+            stream_url: STREAM_URL.to_string(),
+        }
+    }
+}
+
+impl Endpoint {
+    pub fn local() -> Self {
+        Self {
+            api_url: LOCAL_API_URL.to_string(),
+            stream_url: String::new(),
+        }
+    }
+
+    fn url(&self, path: impl AsRef<str>) -> String {
+        format!("{}{}", self.api_url.trim_end_matches('/'), path.as_ref())
+    }
+}
 
-        let mut headers = HeaderMap::new();
-        headers.insert("Zotero-API-Version", "3".parse().unwrap());
-        client_builder = client_builder.default_headers(headers);
+fn build_client() -> Client {
+    let mut client_builder = ClientBuilder::new();
 
-        client_builder
-            .build()
-            .expect("failed to create zotero client")
-    });
-    &CLIENT
+    let mut headers = HeaderMap::new();
+    headers.insert("Zotero-API-Version", "3".parse().unwrap());
+    client_builder = client_builder.default_headers(headers);
+
+    client_builder
+        .build()
+        .expect("failed to create zotero client")
 }
 
 #[derive(Debug, Clone)]
 pub struct Zotero {
-    client: &'static Client,
-    api_key: Arc<ZoteroApiKey>,
+    client: Client,
+    endpoint: Arc<Endpoint>,
+    /// `None` for the local api, which needs no key
+    api_key: Option<Arc<ZoteroApiKey>>,
     user_id: i64,
     pub user_name: Arc<UserName>,
+    access: Arc<Access>,
 }
 
 impl Zotero {
-    pub async fn new(api_key: ZoteroApiKey) -> Result<Self, ZoteroError> {
-        let client = client();
-        let url = format!("{}/keys/{}", BASE_URL, api_key);
+    #[allow(dead_code)]
+    pub async fn new(endpoint: Endpoint, api_key: ZoteroApiKey) -> Result<Self, ZoteroError> {
+        let info = Self::key_info(&endpoint, &api_key).await?;
+        Ok(Self::from_key_info(endpoint, api_key, &info))
+    }
+
+    /// build the client from a cached [`KeyInfo`] without asking the api
+    pub fn from_key_info(endpoint: Endpoint, api_key: ZoteroApiKey, info: &KeyInfo) -> Self {
+        Self {
+            client: build_client(),
+            endpoint: Arc::new(endpoint),
+            api_key: Some(Arc::new(api_key)),
+            user_id: info.user_id,
+            user_name: Arc::new(info.user_name.clone()),
+            access: Arc::new(info.access.clone()),
+        }
+    }
+
+    /// client of the local api of zotero desktop, see [`LOCAL_API_URL`]
+    pub fn local(endpoint: Endpoint) -> Self {
+        Self {
+            client: build_client(),
+            endpoint: Arc::new(endpoint),
+            api_key: None,
+            user_id: LOCAL_USER_ID,
+            user_name: Arc::new("local".to_string()),
+            access: Arc::new(Access::default()),
+        }
+    }
+
+    pub fn is_local(&self) -> bool {
+        self.api_key.is_none()
+    }
+
+    /// fails when zotero desktop is not running or its local api is disabled
+    pub async fn ping(&self) -> Result<(), ZoteroError> {
+        let url = self
+            .endpoint
+            .url(format!("/users/{}/collections?limit=1", self.user_id));
+        let response = self.get(url).send().await?;
+        if !response.status().is_success() {
+            return Err(ZoteroError::RequestInvalid(response.status()));
+        }
+        Ok(())
+    }
+
+    fn get(&self, url: impl reqwest::IntoUrl) -> RequestBuilder {
+        self.authorized(self.client.get(url))
+    }
+
+    fn authorized(&self, request: RequestBuilder) -> RequestBuilder {
+        match &self.api_key {
+            Some(api_key) => request.bearer_auth(api_key.as_ref()),
+            None => request,
+        }
+    }
+
+    /// look up the user and permissions of `api_key`
+    pub async fn key_info(
+        endpoint: &Endpoint,
+        api_key: &ZoteroApiKey,
+    ) -> Result<KeyInfo, ZoteroError> {
+        let client = build_client();
+        let url = endpoint.url(format!("/keys/{}", api_key));
         let response = client.get(url).send().await?;
 
         if !response.status().is_success() && response.status() != reqwest::StatusCode::NOT_MODIFIED
@@ -61,20 +163,15 @@ impl Zotero {
             }
         };
 
-        if !key_resp.access.user.files
-            || !key_resp.access.user.library
-            || !key_resp.access.user.notes
-        {
-            return Err(ZoteroError::ApiKey(
-                "no access to files, library or notes".into(),
-            ));
+        // without file, note or write access the app still works, see [`Capabilities`]
+        if !key_resp.access.user.library {
+            return Err(ZoteroError::InsufficientAccess);
         }
 
-        Ok(Self {
-            client,
-            api_key: Arc::new(api_key),
+        Ok(KeyInfo {
             user_id: key_resp.user_id,
-            user_name: Arc::new(key_resp.user_name),
+            user_name: key_resp.user_name,
+            access: key_resp.access,
         })
     }
 
@@ -82,15 +179,10 @@ impl Zotero {
     where
         T: serde::de::DeserializeOwned,
     {
-        let url = format!("{}/users/{}{}", BASE_URL, self.user_id, path.as_ref());
-        let response = self
-            .client
-            .get(&url)
-            .bearer_auth(self.api_key.as_ref())
-            .send()
-            .await?
-            .text()
-            .await?;
+        let url = self
+            .endpoint
+            .url(format!("/users/{}{}", self.user_id, path.as_ref()));
+        let response = self.get(&url).send().await?.text().await?;
         match serde_json::from_str(&response) {
             Ok(resp) => Ok(resp),
             Err(e) => {
@@ -100,6 +192,131 @@ impl Zotero {
         }
     }
 
+    /// like [`Zotero::user_get`] for the parts of the api that are not about a
+    /// library, such as the item type schema
+    pub async fn schema_get<T>(&self, path: impl AsRef<str>) -> Result<T, ZoteroError>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        let url = self.endpoint.url(path);
+        let response = self.get(&url).send().await?;
+        if !response.status().is_success() {
+            return Err(ZoteroError::RequestInvalid(response.status()));
+        }
+        let response = response.text().await?;
+        match serde_json::from_str(&response) {
+            Ok(resp) => Ok(resp),
+            Err(e) => {
+                error!("resp data: {}, url: {}, error: {:?}", response, url, e);
+                Err(ZoteroError::Data(e))
+            }
+        }
+    }
+
+    /// like [`Zotero::user_get`], also returns the `Last-Modified-Version` of the library
+    pub async fn user_get_versioned<T>(
+        &self,
+        path: impl AsRef<str>,
+    ) -> Result<Versioned<T>, ZoteroError>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        let url = self
+            .endpoint
+            .url(format!("/users/{}{}", self.user_id, path.as_ref()));
+        let response = self.get(&url).send().await?;
+        if !response.status().is_success() {
+            return Err(ZoteroError::RequestInvalid(response.status()));
+        }
+        let version = response
+            .headers()
+            .get("Last-Modified-Version")
+            .and_then(|x| x.to_str().ok())
+            .and_then(|x| x.parse().ok())
+            .unwrap_or_default();
+        let response = response.text().await?;
+        match serde_json::from_str(&response) {
+            Ok(data) => Ok(Versioned { data, version }),
+            Err(e) => {
+                error!("resp data: {}, url: {}, error: {:?}", response, url, e);
+                Err(ZoteroError::Data(e))
+            }
+        }
+    }
+
+    /// like [`Zotero::user_get_versioned`] for lists, every page of `Total-Results`
+    /// is read. the oldest version of the pages is returned, so what changed while
+    /// paging is fetched again next time.
+    pub async fn user_get_all<T>(
+        &self,
+        path: impl AsRef<str>,
+    ) -> Result<Versioned<Vec<T>>, ZoteroError>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        let path = path.as_ref();
+        let separator = if path.contains('?') { '&' } else { '?' };
+        let mut data = vec![];
+        let mut version = i64::MAX;
+        loop {
+            let url = self.endpoint.url(format!(
+                "/users/{}{}{}limit={}&start={}",
+                self.user_id,
+                path,
+                separator,
+                PAGE_SIZE,
+                data.len()
+            ));
+            let response = self.get(&url).send().await?;
+            if !response.status().is_success() {
+                return Err(ZoteroError::RequestInvalid(response.status()));
+            }
+            let header = |name: &str| {
+                response
+                    .headers()
+                    .get(name)
+                    .and_then(|x| x.to_str().ok())
+                    .and_then(|x| x.parse::<i64>().ok())
+            };
+            version = version.min(header("Last-Modified-Version").unwrap_or_default());
+            let total = header("Total-Results");
+            let response = response.text().await?;
+            let page: Vec<T> = match serde_json::from_str(&response) {
+                Ok(page) => page,
+                Err(e) => {
+                    error!("resp data: {}, url: {}, error: {:?}", response, url, e);
+                    return Err(ZoteroError::Data(e));
+                }
+            };
+            let count = page.len();
+            data.extend(page);
+            let done = match total {
+                Some(total) => data.len() as i64 >= total,
+                None => count < PAGE_SIZE,
+            };
+            if count == 0 || done {
+                return Ok(Versioned { data, version });
+            }
+        }
+    }
+
+    /// keys of objects deleted since `version`
+    pub async fn get_deleted(&self, version: i64) -> Result<Versioned<Deleted>, ZoteroError> {
+        self.user_get_versioned(format!("/deleted?since={}", version))
+            .await
+    }
+
+    pub fn user_id(&self) -> i64 {
+        self.user_id
+    }
+
+    pub fn capabilities(&self) -> Capabilities {
+        if self.is_local() {
+            return Capabilities::local();
+        }
+        Capabilities::new(&self.access)
+    }
+
     pub async fn user_post<T>(
         &self,
         path: impl AsRef<str>,
@@ -110,17 +327,14 @@ impl Zotero {
     where
         T: serde::de::DeserializeOwned,
     {
-        let url = format!(
-            "{}/users/{}{}/{}/file",
-            BASE_URL,
+        let url = self.endpoint.url(format!(
+            "/users/{}{}/{}/file",
             self.user_id,
             path.as_ref(),
             key.as_ref()
-        );
+        ));
         let response = self
-            .client
-            .post(&url)
-            .bearer_auth(self.api_key.as_ref())
+            .authorized(self.client.post(&url))
             .header("If-Match", old_md5.as_ref())
             .form(form)
             .send()
@@ -154,18 +368,16 @@ impl Zotero {
             "contentType",
             reqwest::multipart::Part::text(auth_resp.content_type.clone()),
         );
+        let request = self.client.post(self.endpoint.url(format!(
+            "/users/{}/items/{}/file",
+            self.user_id,
+            key.as_ref()
+        )));
         let response = self
-            .client
-            .post(format!(
-                "{}/users/{}/items/{}/file",
-                BASE_URL,
-                self.user_id,
-                key.as_ref()
-            ))
+            .authorized(request)
             .header("Content-Type", auth_resp.content_type)
             .body("test")
             .multipart(form)
-            .bearer_auth(&self.api_key)
             .header("If-Match", old_md5.as_ref())
             .send()
             .await?;
@@ -181,16 +393,16 @@ mod tests {
     #[cfg(feature = "__local_test__")]
     #[tokio::test]
     async fn test_get_user_id() {
-        let client = client();
+        let client = build_client();
 
-        let url = format!("{}/keys/{}", BASE_URL, dotenv!("ZOTERO_API_KEY"));
+        let url = Endpoint::default().url(format!("/keys/{}", dotenv!("ZOTERO_API_KEY")));
         let response = client.get(url).send().await.unwrap();
         println!("{:?}", response.text().await);
     }
 
     #[tokio::test]
     async fn test_no_auth() {
-        let zotero = Zotero::new("".into()).await;
+        let zotero = Zotero::new(Endpoint::default(), "".into()).await;
         println!("{:?}", zotero);
         assert!(matches!(
             zotero.unwrap_err(),
@@ -198,10 +410,87 @@ mod tests {
         ));
     }
 
+    #[tokio::test]
+    async fn test_local_endpoint() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = Endpoint {
+            api_url: format!("http://{}/api/", listener.local_addr().unwrap()),
+            ..Default::default()
+        };
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = vec![0u8; 4096];
+            let n = stream.read(&mut buf).await.unwrap();
+            let request = String::from_utf8_lossy(&buf[..n]).to_lowercase();
+            assert!(request.starts_with("get /api/keys/local-key "));
+            assert!(request.contains("zotero-api-version: 3"));
+            let body =
+                r#"{"userID": 7, "username": "local", "access": {"user": {"library": true}}}"#;
+            let response = format!(
+                "HTTP/1.1 200 OK\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            stream.write_all(response.as_bytes()).await.unwrap();
+        });
+
+        let zotero = Zotero::new(endpoint, "local-key".into()).await.unwrap();
+        assert_eq!(zotero.user_id(), 7);
+        assert!(zotero
+            .capabilities()
+            .unavailable(model::Feature::Download)
+            .is_some());
+    }
+
+    #[tokio::test]
+    async fn test_user_get_all() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = Endpoint {
+            api_url: format!("http://{}", listener.local_addr().unwrap()),
+            ..Default::default()
+        };
+        tokio::spawn(async move {
+            // 150 objects in two pages, the library changes before the second one
+            for (start, count, version) in [(0, 100, 11), (100, 50, 12)] {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buf = vec![0u8; 4096];
+                let n = stream.read(&mut buf).await.unwrap();
+                let request = String::from_utf8_lossy(&buf[..n]).to_string();
+                let path = format!("GET /users/7/items?since=3&limit=100&start={} ", start);
+                assert!(request.starts_with(&path), "{}", request);
+                let body = serde_json::to_string(&vec![start; count]).unwrap();
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\ntotal-results: 150\r\nlast-modified-version: {}\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                    version,
+                    body.len(),
+                    body
+                );
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+
+        let info = KeyInfo {
+            user_id: 7,
+            user_name: "paging".to_string(),
+            access: Default::default(),
+        };
+        let zotero = Zotero::from_key_info(endpoint, "key".into(), &info);
+        let all: Versioned<Vec<i64>> = zotero.user_get_all("/items?since=3").await.unwrap();
+        assert_eq!(all.data.len(), 150);
+        assert_eq!(all.data[149], 100);
+        assert_eq!(all.version, 11);
+    }
+
     #[cfg(feature = "__local_test__")]
     #[tokio::test]
     async fn test_new() {
-        let zotero = Zotero::new(dotenv!("ZOTERO_API_KEY").into()).await.unwrap();
+        let zotero = Zotero::new(Endpoint::default(), dotenv!("ZOTERO_API_KEY").into())
+            .await
+            .unwrap();
         println!("{:?}", zotero);
     }
 }
@@ -1,6 +1,10 @@
-use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::Path;
 
-use crate::model::auth::Secret;
+use serde::{Deserialize, Serialize};
+
+use super::error::ZoteroError;
+use crate::model::auth::{Secret, UserName};
 
 #[derive(Debug, Deserialize)]
 pub(super) struct KeyResp {
@@ -12,13 +16,36 @@ pub(super) struct KeyResp {
     pub access: Access,
 }
 
-#[derive(Default, Debug, Deserialize)]
+/// what the api reported about a key, cached so the app can start without network
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyInfo {
+    pub user_id: i64,
+    pub user_name: UserName,
+    pub access: Access,
+}
+
+impl KeyInfo {
+    pub fn load(path: &Path) -> Option<Self> {
+        std::fs::read_to_string(path)
+            .ok()
+            .and_then(|x| serde_json::from_str(&x).ok())
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        std::fs::write(path, serde_json::to_string(self)?)?;
+        Ok(())
+    }
+}
+
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
 #[serde(default, rename_all = "camelCase")]
 pub struct Access {
     pub user: User,
+    /// by group id, `all` applies to every group of the user
+    pub groups: BTreeMap<String, GroupAccess>,
 }
 
-#[derive(Default, Debug, Deserialize)]
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
 #[serde(default, rename_all = "camelCase")]
 pub struct User {
     pub library: bool,
@@ -27,4 +54,170 @@ pub struct User {
     pub write: bool,
 }
 
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct GroupAccess {
+    pub library: bool,
+    pub write: bool,
+}
+
+/// a part of the app that needs more than read access to the library
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Feature {
+    Notes,
+    Download,
+    Upload,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Disabled {
+    pub feature: Feature,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GroupCapabilities {
+    /// `None` for the access given to all groups
+    pub group_id: Option<i64>,
+    pub library: bool,
+    pub write: bool,
+}
+
+/// what the app can do with a key, keys without file or write access are
+/// accepted with the features needing them disabled
+#[derive(Debug, Clone, Serialize)]
+pub struct Capabilities {
+    pub library: bool,
+    pub notes: bool,
+    pub files: bool,
+    pub write: bool,
+    pub groups: Vec<GroupCapabilities>,
+    pub disabled: Vec<Disabled>,
+}
+
+impl Capabilities {
+    pub fn new(access: &Access) -> Self {
+        let user = &access.user;
+        let mut disabled = vec![];
+        if !user.notes {
+            disabled.push(Disabled {
+                feature: Feature::Notes,
+                reason: "the api key has no access to notes, they are left out of the library"
+                    .to_string(),
+            });
+        }
+        if !user.files {
+            for feature in [Feature::Download, Feature::Upload] {
+                disabled.push(Disabled {
+                    feature,
+                    reason: "the api key has no access to attachment files".to_string(),
+                });
+            }
+        } else if !user.write {
+            disabled.push(Disabled {
+                feature: Feature::Upload,
+                reason: "the api key is read only, local changes are not uploaded".to_string(),
+            });
+        }
+
+        let groups = access
+            .groups
+            .iter()
+            .map(|(id, x)| GroupCapabilities {
+                group_id: id.parse().ok(),
+                library: x.library,
+                write: x.write,
+            })
+            .collect();
+
+        Self {
+            library: user.library,
+            notes: user.notes,
+            files: user.files,
+            write: user.write,
+            groups,
+            disabled,
+        }
+    }
+
+    /// the local api of zotero desktop is read only, attachments are opened from
+    /// the desktop storage directory instead of being synced
+    pub fn local() -> Self {
+        let reason =
+            "the local zotero library is read only, files are opened from its storage directory";
+        Self {
+            library: true,
+            notes: true,
+            files: true,
+            write: false,
+            groups: vec![],
+            disabled: [Feature::Download, Feature::Upload]
+                .into_iter()
+                .map(|feature| Disabled {
+                    feature,
+                    reason: reason.to_string(),
+                })
+                .collect(),
+        }
+    }
+
+    /// why `feature` is disabled, `None` when it is available
+    pub fn unavailable(&self, feature: Feature) -> Option<&str> {
+        self.disabled
+            .iter()
+            .find(|x| x.feature == feature)
+            .map(|x| x.reason.as_str())
+    }
+
+    pub fn check(&self, feature: Feature) -> Result<(), ZoteroError> {
+        match self.unavailable(feature) {
+            Some(reason) => Err(ZoteroError::Unavailable(reason.to_string())),
+            None => Ok(()),
+        }
+    }
+}
+
 pub type ZoteroApiKey = Secret;
+
+/// response data together with the library version it was read at
+#[derive(Debug)]
+pub struct Versioned<T> {
+    pub data: T,
+    pub version: i64,
+}
+
+#[derive(Default, Debug, Deserialize)]
+#[serde(default)]
+pub struct Deleted {
+    pub collections: Vec<String>,
+    pub items: Vec<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_capabilities() {
+        let access: Access = serde_json::from_str(
+            r#"{"user": {"library": true, "files": true, "notes": true},
+                "groups": {"all": {"library": true}, "123": {"library": true, "write": true}}}"#,
+        )
+        .unwrap();
+        let capabilities = Capabilities::new(&access);
+        assert!(capabilities.check(Feature::Download).is_ok());
+        assert!(capabilities.unavailable(Feature::Upload).is_some());
+        assert_eq!(capabilities.groups.len(), 2);
+        assert_eq!(capabilities.groups[0].group_id, Some(123));
+        assert!(capabilities.groups[0].write);
+        assert_eq!(capabilities.groups[1].group_id, None);
+
+        let access: Access =
+            serde_json::from_str(r#"{"user": {"library": true, "write": true}}"#).unwrap();
+        let capabilities = Capabilities::new(&access);
+        assert!(capabilities.check(Feature::Download).is_err());
+        assert!(capabilities.check(Feature::Upload).is_err());
+        assert!(capabilities.unavailable(Feature::Notes).is_some());
+    }
+}
@@ -0,0 +1,429 @@
+use std::time::Duration;
+
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::OsRng;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use reqwest::Url;
+use sha1::Sha1;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tracing::{debug, warn};
+
+use super::error::ZoteroError;
+use super::model::ZoteroApiKey;
+use crate::model::auth::{Secret, UserName};
+
+const OAUTH_URL: &str = "https://www.zotero.org";
+const CALLBACK_PATH: &str = "/oauth/callback";
+/// requests to the callback server larger than this are rejected
+const MAX_CALLBACK_REQUEST: usize = 8 * 1024;
+/// connections to the callback server that send nothing for this long are closed
+const CALLBACK_READ_TIMEOUT: Duration = Duration::from_secs(5);
+const CALLBACK_PAGE: &str = "<html><body>login finished, you can return to the app</body></html>";
+
+/// characters other than the unreserved ones are encoded, as rfc 5849 requires
+const ENCODE_SET: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+#[derive(Debug, Clone)]
+pub struct RequestToken {
+    pub token: String,
+    pub secret: Secret,
+}
+
+/// zotero returns the api key as the secret of the access token
+#[derive(Debug, Clone)]
+pub struct AccessToken {
+    pub api_key: ZoteroApiKey,
+    pub user_id: i64,
+    pub user_name: UserName,
+}
+
+/// oauth 1.0a client of the zotero website, which hands out api keys
+#[derive(Debug, Clone)]
+pub struct OAuth {
+    base_url: String,
+    client_key: String,
+    client_secret: Secret,
+}
+
+impl OAuth {
+    pub fn new(
+        base_url: impl Into<String>,
+        client_key: impl Into<String>,
+        client_secret: Secret,
+    ) -> Self {
+        Self {
+            base_url: base_url.into(),
+            client_key: client_key.into(),
+            client_secret,
+        }
+    }
+
+    /// the client registered for this app at zotero.org. its key and secret are
+    /// read from `ZOTERO_OAUTH_CLIENT_KEY` and `ZOTERO_OAUTH_CLIENT_SECRET`, set when
+    /// building or when starting the app.
+    pub fn zotero() -> Result<Self, ZoteroError> {
+        let var = |name: &str, built: Option<&str>| {
+            built
+                .map(|x| x.to_string())
+                .or_else(|| std::env::var(name).ok())
+                .filter(|x| !x.is_empty())
+        };
+        let (Some(key), Some(secret)) = (
+            var(
+                "ZOTERO_OAUTH_CLIENT_KEY",
+                option_env!("ZOTERO_OAUTH_CLIENT_KEY"),
+            ),
+            var(
+                "ZOTERO_OAUTH_CLIENT_SECRET",
+                option_env!("ZOTERO_OAUTH_CLIENT_SECRET"),
+            ),
+        ) else {
+            return Err(ZoteroError::OAuth(
+                "oauth is not configured, ZOTERO_OAUTH_CLIENT_KEY and ZOTERO_OAUTH_CLIENT_SECRET are missing".to_string(),
+            ));
+        };
+        Ok(Self::new(OAUTH_URL, key, secret.into()))
+    }
+
+    /// first step, `callback` is where the authorize page redirects to
+    pub async fn request_token(&self, callback: &str) -> Result<RequestToken, ZoteroError> {
+        let resp = self
+            .post("/oauth/request", &[("oauth_callback", callback)], "")
+            .await?;
+        Ok(RequestToken {
+            token: field(&resp, "oauth_token")?,
+            secret: field(&resp, "oauth_token_secret")?.into(),
+        })
+    }
+
+    /// page where the user grants access, the key gets the permissions this app needs
+    pub fn authorize_url(&self, token: &RequestToken) -> String {
+        let mut url = Url::parse(&format!("{}/oauth/authorize", self.base_url))
+            .expect("invalid oauth base url");
+        url.query_pairs_mut()
+            .append_pair("oauth_token", &token.token)
+            .append_pair("library_access", "1")
+            .append_pair("notes_access", "1")
+            .append_pair("write_access", "1")
+            .append_pair("all_groups", "write");
+        url.to_string()
+    }
+
+    /// last step, trade the authorized request token for an api key
+    pub async fn access_token(
+        &self,
+        token: &RequestToken,
+        verifier: &str,
+    ) -> Result<AccessToken, ZoteroError> {
+        let resp = self
+            .post(
+                "/oauth/access",
+                &[("oauth_token", &token.token), ("oauth_verifier", verifier)],
+                token.secret.as_ref(),
+            )
+            .await?;
+        let user_id = field(&resp, "userID")?;
+        Ok(AccessToken {
+            api_key: field(&resp, "oauth_token_secret")?.into(),
+            user_id: user_id
+                .parse()
+                .map_err(|_| ZoteroError::OAuth(format!("invalid user id: {}", user_id)))?,
+            user_name: field(&resp, "username")?,
+        })
+    }
+
+    async fn post(
+        &self,
+        path: &str,
+        extra: &[(&str, &str)],
+        token_secret: &str,
+    ) -> Result<Vec<(String, String)>, ZoteroError> {
+        let url = format!("{}{}", self.base_url, path);
+        let mut nonce = [0u8; 16];
+        OsRng.fill_bytes(&mut nonce);
+        let nonce = hex::encode(nonce);
+        let timestamp = chrono::Utc::now().timestamp().to_string();
+
+        let mut params = vec![
+            ("oauth_consumer_key", self.client_key.as_str()),
+            ("oauth_nonce", nonce.as_str()),
+            ("oauth_signature_method", "HMAC-SHA1"),
+            ("oauth_timestamp", timestamp.as_str()),
+            ("oauth_version", "1.0"),
+        ];
+        params.extend_from_slice(extra);
+        let signature = signature(
+            "POST",
+            &url,
+            &params,
+            self.client_secret.as_ref(),
+            token_secret,
+        );
+        params.push(("oauth_signature", &signature));
+        let header = params
+            .iter()
+            .map(|(k, v)| format!("{}=\"{}\"", encode(k), encode(v)))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        let response = super::build_client()
+            .post(&url)
+            .header(reqwest::header::AUTHORIZATION, format!("OAuth {}", header))
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(ZoteroError::RequestInvalid(response.status()));
+        }
+        let body = response.text().await?;
+        Ok(url::form_urlencoded::parse(body.as_bytes())
+            .into_owned()
+            .collect())
+    }
+}
+
+fn field(resp: &[(String, String)], name: &str) -> Result<String, ZoteroError> {
+    resp.iter()
+        .find(|(k, _)| k == name)
+        .map(|(_, v)| v.clone())
+        .ok_or_else(|| ZoteroError::OAuth(format!("{} missing in response", name)))
+}
+
+fn encode(s: &str) -> String {
+    utf8_percent_encode(s, ENCODE_SET).to_string()
+}
+
+/// hmac-sha1 signature of a request, rfc 5849 section 3.4
+fn signature(
+    method: &str,
+    url: &str,
+    params: &[(&str, &str)],
+    consumer_secret: &str,
+    token_secret: &str,
+) -> String {
+    let mut params: Vec<(String, String)> =
+        params.iter().map(|(k, v)| (encode(k), encode(v))).collect();
+    params.sort();
+    let params = params
+        .iter()
+        .map(|(k, v)| format!("{}={}", k, v))
+        .collect::<Vec<_>>()
+        .join("&");
+    let base = format!("{}&{}&{}", method, encode(url), encode(&params));
+    let key = format!("{}&{}", encode(consumer_secret), encode(token_secret));
+
+    let mut mac = Hmac::<Sha1>::new_from_slice(key.as_bytes()).expect("hmac takes any key size");
+    mac.update(base.as_bytes());
+    base64::engine::general_purpose::STANDARD.encode(mac.finalize().into_bytes())
+}
+
+/// loopback http server the authorize page redirects back to
+pub struct CallbackServer {
+    listener: TcpListener,
+}
+
+impl CallbackServer {
+    pub async fn bind() -> std::io::Result<Self> {
+        Ok(Self {
+            listener: TcpListener::bind("127.0.0.1:0").await?,
+        })
+    }
+
+    pub fn url(&self) -> std::io::Result<String> {
+        Ok(format!(
+            "http://{}{}",
+            self.listener.local_addr()?,
+            CALLBACK_PATH
+        ))
+    }
+
+    /// wait for the redirect of `token` and return its verifier, other requests are answered
+    /// with 404. every connection is served on its own, so idle ones such as the
+    /// preconnects of browsers do not hold up the redirect.
+    pub async fn wait(&self, token: &RequestToken) -> Result<String, ZoteroError> {
+        let (tx, mut rx) = mpsc::channel(1);
+        loop {
+            tokio::select! {
+                accepted = self.listener.accept() => {
+                    let (stream, _) = accepted.map_err(|e| ZoteroError::OAuth(e.to_string()))?;
+                    let (tx, token) = (tx.clone(), token.token.clone());
+                    tokio::spawn(async move {
+                        if let Some(verifier) = serve_callback(stream, &token).await {
+                            tx.send(verifier).await.ok();
+                        }
+                    });
+                }
+                Some(verifier) = rx.recv() => return Ok(verifier),
+            }
+        }
+    }
+}
+
+/// answer one request to the callback server, the verifier when it is the redirect
+/// of `token`
+async fn serve_callback(mut stream: TcpStream, token: &str) -> Option<String> {
+    let mut buf = vec![];
+    let mut chunk = [0u8; 1024];
+    while !buf.windows(4).any(|x| x == b"\r\n\r\n") && buf.len() < MAX_CALLBACK_REQUEST {
+        match tokio::time::timeout(CALLBACK_READ_TIMEOUT, stream.read(&mut chunk)).await {
+            Ok(Ok(0)) | Ok(Err(_)) => break,
+            Ok(Ok(n)) => buf.extend_from_slice(&chunk[..n]),
+            Err(_) => {
+                debug!("oauth callback connection idle, closed");
+                return None;
+            }
+        }
+    }
+    let request = String::from_utf8_lossy(&buf);
+    let target = request
+        .lines()
+        .next()
+        .and_then(|x| x.split_whitespace().nth(1))
+        .unwrap_or_default();
+    debug!("oauth callback request: {}", target);
+
+    let query = Url::parse(&format!("http://localhost{}", target))
+        .ok()
+        .filter(|x| x.path() == CALLBACK_PATH)
+        .map(|x| x.query_pairs().into_owned().collect::<Vec<_>>());
+    let verifier = query.as_ref().and_then(|query| {
+        let token_matches = query.iter().any(|(k, v)| k == "oauth_token" && v == token);
+        query
+            .iter()
+            .find(|(k, _)| k == "oauth_verifier")
+            .filter(|_| token_matches)
+            .map(|(_, v)| v.clone())
+    });
+
+    let response = match verifier {
+        Some(_) => format!(
+            "HTTP/1.1 200 OK\r\ncontent-type: text/html\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+            CALLBACK_PAGE.len(),
+            CALLBACK_PAGE
+        ),
+        None => "HTTP/1.1 404 Not Found\r\ncontent-length: 0\r\nconnection: close\r\n\r\n"
+            .to_string(),
+    };
+    if let Err(e) = stream.write_all(response.as_bytes()).await {
+        warn!("answer oauth callback failed: {:?}", e);
+    }
+    verifier
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signature() {
+        // example of the oauth core 1.0 spec, appendix a.5
+        let params = [
+            ("file", "vacation.jpg"),
+            ("size", "original"),
+            ("oauth_consumer_key", "dpf43f3p2l4k3l03"),
+            ("oauth_token", "nnch734d00sl2jdk"),
+            ("oauth_signature_method", "HMAC-SHA1"),
+            ("oauth_timestamp", "1191242096"),
+            ("oauth_nonce", "kllo9940pd9333jh"),
+            ("oauth_version", "1.0"),
+        ];
+        assert_eq!(
+            signature(
+                "GET",
+                "http://photos.example.net/photos",
+                &params,
+                "kd94hf93k423kf44",
+                "pfkkdhi9sl3r4s00"
+            ),
+            "tR3+Ty81lMeYAr/Fid0kMTYa/WM="
+        );
+    }
+
+    /// answers the two token requests like zotero.org does
+    async fn stand_in_server() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buf = vec![0u8; 4096];
+                let n = stream.read(&mut buf).await.unwrap();
+                let request = String::from_utf8_lossy(&buf[..n]).to_string();
+                let body = if request.starts_with("POST /oauth/request ") {
+                    assert!(request.contains("oauth_callback="));
+                    "oauth_token=request-token&oauth_token_secret=request-secret&oauth_callback_confirmed=true"
+                } else if request.starts_with("POST /oauth/access ") {
+                    assert!(request.contains("oauth_token=\"request-token\""));
+                    assert!(request.contains("oauth_verifier=\"the-verifier\""));
+                    "oauth_token=access-token&oauth_token_secret=the-api-key&userID=42&username=someone"
+                } else {
+                    ""
+                };
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        url
+    }
+
+    #[tokio::test]
+    async fn test_oauth_flow() {
+        let oauth = OAuth::new(stand_in_server().await, "client", "client-secret".into());
+        let server = CallbackServer::bind().await.unwrap();
+        let callback = server.url().unwrap();
+
+        let token = oauth.request_token(&callback).await.unwrap();
+        assert_eq!(token.token, "request-token");
+        assert!(oauth
+            .authorize_url(&token)
+            .contains("oauth_token=request-token"));
+
+        // the browser following the redirect of the authorize page, after an idle
+        // preconnect which must not hold it up
+        let redirect = async {
+            let _idle = TcpStream::connect(
+                callback
+                    .trim_start_matches("http://")
+                    .trim_end_matches(CALLBACK_PATH),
+            )
+            .await
+            .unwrap();
+            let client = reqwest::Client::new();
+            let wrong = client
+                .get(format!("{}?oauth_token=other&oauth_verifier=x", callback))
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(wrong.status(), reqwest::StatusCode::NOT_FOUND);
+            client
+                .get(format!(
+                    "{}?oauth_token=request-token&oauth_verifier=the-verifier",
+                    callback
+                ))
+                .send()
+                .await
+                .unwrap()
+                .status()
+        };
+        let (verifier, status) = tokio::join!(server.wait(&token), redirect);
+        assert!(status.is_success());
+        let verifier = verifier.unwrap();
+        assert_eq!(verifier, "the-verifier");
+
+        let access = oauth.access_token(&token, &verifier).await.unwrap();
+        assert_eq!(access.api_key.as_ref(), "the-api-key");
+        assert_eq!(access.user_id, 42);
+        assert_eq!(access.user_name, "someone");
+    }
+}
@@ -0,0 +1,264 @@
+use std::time::Duration;
+
+use futures_util::{SinkExt, StreamExt};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+use tracing::{debug, info, warn};
+
+use super::error::ZoteroError;
+use super::model::ZoteroApiKey;
+use super::Zotero;
+
+/// wait before reconnecting until the server sent a `retry` hint
+const DEFAULT_RETRY: Duration = Duration::from_secs(10);
+/// failed reconnects back off up to this
+const MAX_RETRY: Duration = Duration::from_secs(5 * 60);
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// what the streaming api reported about a subscribed topic, such as `/users/1`
+/// or `/groups/2`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StreamEvent {
+    /// the library behind `topic` is at `version` now
+    TopicUpdated {
+        topic: String,
+        version: i64,
+    },
+    /// the key got access to `topic`, a group was joined for example
+    TopicAdded {
+        topic: String,
+    },
+    TopicRemoved {
+        topic: String,
+    },
+    /// connected again after a drop, updates in between may have been missed
+    Reconnected,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "event", rename_all = "camelCase")]
+enum ServerMessage {
+    Connected,
+    SubscriptionsCreated {
+        #[serde(default)]
+        errors: Vec<Value>,
+    },
+    TopicUpdated {
+        topic: String,
+        version: i64,
+    },
+    TopicAdded {
+        topic: String,
+    },
+    TopicRemoved {
+        topic: String,
+    },
+    #[serde(other)]
+    Other,
+}
+
+/// client of the zotero streaming api, see <https://www.zotero.org/support/dev/web_api/v3/streaming_api>
+pub struct Stream {
+    url: String,
+    api_key: ZoteroApiKey,
+    retry: Duration,
+}
+
+impl Zotero {
+    /// `None` for the local api and endpoints without a stream url
+    pub fn stream(&self) -> Option<Stream> {
+        let api_key = self.api_key.as_ref()?;
+        if self.endpoint.stream_url.is_empty() {
+            return None;
+        }
+        Some(Stream {
+            url: self.endpoint.stream_url.clone(),
+            api_key: (**api_key).clone(),
+            retry: DEFAULT_RETRY,
+        })
+    }
+}
+
+impl Stream {
+    /// subscribe to every topic of the key, its user library and groups, and send
+    /// the events to `tx`. drops are reconnected after the server's `retry` hint,
+    /// doubled for every failed attempt. returns once `tx` is closed.
+    pub async fn run(mut self, tx: mpsc::Sender<StreamEvent>) {
+        let mut attempts = 0u32;
+        loop {
+            match self.connect().await {
+                Ok(mut socket) => {
+                    if attempts > 0 && tx.send(StreamEvent::Reconnected).await.is_err() {
+                        return;
+                    }
+                    attempts = 0;
+                    match self.session(&mut socket, &tx).await {
+                        Ok(()) => return,
+                        Err(e) => warn!("stream disconnected: {}", e),
+                    }
+                }
+                Err(e) => warn!("stream connect failed: {}", e),
+            }
+            attempts += 1;
+            let wait = self
+                .retry
+                .saturating_mul(1 << (attempts - 1).min(8))
+                .min(MAX_RETRY);
+            debug!("stream reconnects in {:?}", wait);
+            tokio::select! {
+                _ = tokio::time::sleep(wait) => {}
+                _ = tx.closed() => return,
+            }
+        }
+    }
+
+    async fn connect(&mut self) -> Result<Socket, ZoteroError> {
+        let (mut socket, _) = tokio_tungstenite::connect_async(self.url.as_str())
+            .await
+            .map_err(|e| ZoteroError::Stream(e.to_string()))?;
+        let subscribe = json!({
+            "action": "createSubscriptions",
+            // without topics the key is subscribed to all of its libraries, and
+            // to groups it joins later
+            "subscriptions": [{ "apiKey": self.api_key.as_ref() }],
+        });
+        socket
+            .send(Message::text(subscribe.to_string()))
+            .await
+            .map_err(|e| ZoteroError::Stream(e.to_string()))?;
+        info!("stream connected to {}", self.url);
+        Ok(socket)
+    }
+
+    /// `Ok` when `tx` was closed, `Err` when the connection dropped
+    async fn session(
+        &mut self,
+        socket: &mut Socket,
+        tx: &mpsc::Sender<StreamEvent>,
+    ) -> Result<(), ZoteroError> {
+        loop {
+            let message = tokio::select! {
+                x = socket.next() => x,
+                _ = tx.closed() => {
+                    socket.close(None).await.ok();
+                    return Ok(());
+                }
+            };
+            let text = match message {
+                Some(Ok(Message::Text(x))) => x,
+                Some(Ok(Message::Close(frame))) => {
+                    return Err(ZoteroError::Stream(format!(
+                        "closed by server: {:?}",
+                        frame
+                    )))
+                }
+                Some(Ok(_)) => continue,
+                Some(Err(e)) => return Err(ZoteroError::Stream(e.to_string())),
+                None => return Err(ZoteroError::Stream("connection closed".to_string())),
+            };
+            let value: Value = match serde_json::from_str(&text) {
+                Ok(x) => x,
+                Err(e) => {
+                    warn!("stream message is not json: {}", e);
+                    continue;
+                }
+            };
+            if let Some(retry) = value.get("retry").and_then(|x| x.as_u64()) {
+                self.retry = Duration::from_millis(retry);
+            }
+            let message = match serde_json::from_value(value.clone()) {
+                Ok(x) => x,
+                Err(_) => {
+                    warn!("unknown stream message: {}", value);
+                    continue;
+                }
+            };
+            let event = match message {
+                ServerMessage::TopicUpdated { topic, version } => {
+                    StreamEvent::TopicUpdated { topic, version }
+                }
+                ServerMessage::TopicAdded { topic } => StreamEvent::TopicAdded { topic },
+                ServerMessage::TopicRemoved { topic } => StreamEvent::TopicRemoved { topic },
+                ServerMessage::SubscriptionsCreated { errors } => {
+                    for error in errors {
+                        warn!("stream subscription failed: {}", error);
+                    }
+                    continue;
+                }
+                ServerMessage::Connected | ServerMessage::Other => continue,
+            };
+            debug!("stream event: {:?}", event);
+            if tx.send(event).await.is_err() {
+                socket.close(None).await.ok();
+                return Ok(());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dal::zotero::model::KeyInfo;
+    use crate::dal::zotero::Endpoint;
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn test_stream() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = Endpoint {
+            stream_url: format!("ws://{}", listener.local_addr().unwrap()),
+            ..Default::default()
+        };
+        tokio::spawn(async move {
+            // the first connection is dropped after one update, the client has
+            // to come back after the retry hint
+            for version in [10, 11] {
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut socket = tokio_tungstenite::accept_async(stream).await.unwrap();
+                socket
+                    .send(Message::text(r#"{"event": "connected", "retry": 10}"#))
+                    .await
+                    .unwrap();
+                let subscribe = socket.next().await.unwrap().unwrap();
+                let subscribe: Value = serde_json::from_str(subscribe.to_text().unwrap()).unwrap();
+                assert_eq!(subscribe["action"], "createSubscriptions");
+                assert_eq!(subscribe["subscriptions"][0]["apiKey"], "stream-key");
+                socket
+                    .send(Message::text(
+                        r#"{"event": "subscriptionsCreated", "subscriptions": [], "errors": []}"#,
+                    ))
+                    .await
+                    .unwrap();
+                let update =
+                    json!({"event": "topicUpdated", "topic": "/users/7", "version": version});
+                socket
+                    .send(Message::text(update.to_string()))
+                    .await
+                    .unwrap();
+            }
+        });
+
+        let info = KeyInfo {
+            user_id: 7,
+            user_name: "stream".to_string(),
+            access: Default::default(),
+        };
+        let zotero = Zotero::from_key_info(endpoint, "stream-key".into(), &info);
+        let (tx, mut rx) = mpsc::channel(8);
+        tokio::spawn(zotero.stream().unwrap().run(tx));
+
+        let updated = |version| StreamEvent::TopicUpdated {
+            topic: "/users/7".to_string(),
+            version,
+        };
+        assert_eq!(rx.recv().await, Some(updated(10)));
+        assert_eq!(rx.recv().await, Some(StreamEvent::Reconnected));
+        assert_eq!(rx.recv().await, Some(updated(11)));
+    }
+}
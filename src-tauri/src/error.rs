@@ -23,6 +23,18 @@ pub enum Error {
 
     #[error("[shell]: {0}")]
     Shell(#[from] tauri_plugin_shell::Error),
+
+    #[error("[extract]: {0}")]
+    Extract(#[from] crate::storage::extract::ExtractError),
+
+    #[error("[credentials]: {0}")]
+    Credential(#[from] crate::credentials::error::CredentialError),
+
+    #[error("[library]: {0}")]
+    Library(#[from] crate::dal::library::error::LibraryError),
+
+    #[error("[transfer]: {0}")]
+    Transfer(#[from] crate::transfer::error::TransferError),
 }
 
 impl serde::Serialize for Error {
@@ -40,6 +52,10 @@ impl serde::Serialize for Error {
             Error::Raw(_) => "raw",
             Error::TokioJoin(_) => "tokio_join",
             Error::WebDav(_) => "webdav",
+            Error::Extract(_) => "extract",
+            Error::Transfer(_) => "transfer",
+            Error::Library(_) => "library",
+            Error::Credential(_) => "credential",
         };
         if let Error::Raw(e) = self {
             tracing::error!("[{}] get error: {:?}", err_type, e);
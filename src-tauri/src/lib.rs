@@ -1,18 +1,57 @@
+use std::path::PathBuf;
+
+use credentials::CredentialStore;
+use dal::zotero::error::ZoteroError;
+use dal::zotero::model::{Capabilities, Feature, KeyInfo};
 use dal::zotero::Zotero;
-use model::{auth::Secret, zotero_data::Data};
+use model::{account::Accounts, auth::Secret, settings::Settings, zotero_data::Data};
 use parking_lot::Mutex;
+use storage::pin::Pins;
 use tauri::Manager;
+use transfer::TransferManager;
 
 mod api;
+mod credentials;
 mod dal;
 mod error;
 mod model;
+mod storage;
+mod stream;
+mod transfer;
 
-#[derive(Default)]
 pub(crate) struct AppState {
     pub zotero: Option<Zotero>,
     pub data: Option<Data>,
     pub api_key: Option<Secret>,
+    pub credentials: CredentialStore,
+    pub accounts: Accounts,
+    pub base_directory: Option<PathBuf>,
+    pub settings: Settings,
+    pub pins: Pins,
+    pub transfers: TransferManager,
+}
+
+impl AppState {
+    /// of the current account, the cached key info is used until `is_login` built the client
+    pub fn capabilities(&self) -> Option<Capabilities> {
+        if let Some(zotero) = &self.zotero {
+            return Some(zotero.capabilities());
+        }
+        if self.accounts.current()?.is_local() {
+            return Some(Capabilities::local());
+        }
+        let dir = self.accounts.current_dir()?;
+        KeyInfo::load(&dir.join(model::account::KEY_INFO_FILE))
+            .map(|x| Capabilities::new(&x.access))
+    }
+
+    /// fails with the reason when the key does not allow `feature`
+    pub fn check(&self, feature: Feature) -> Result<(), ZoteroError> {
+        match self.capabilities() {
+            Some(capabilities) => capabilities.check(feature),
+            None => Ok(()),
+        }
+    }
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
@@ -26,21 +65,81 @@ pub fn run() {
             api::get_collections::get_collections,
             api::refresh::refresh,
             api::get_items::get_items_by_collection,
+            api::get_item_detail::get_item_detail,
             api::download_pdf::download_pdf,
             api::is_login::is_login,
+            api::get_attachments::get_attachments,
+            api::base_directory::get_base_directory,
+            api::base_directory::set_base_directory,
+            api::transfers::list_transfers,
+            api::transfers::queue_downloads,
+            api::transfers::cancel_transfer,
+            api::transfers::pause_transfer,
+            api::transfers::resume_transfer,
+            api::settings::get_settings,
+            api::settings::set_settings,
+            api::pin::pin_collection,
+            api::pin::unpin_collection,
+            api::pin::get_pin_status,
+            api::cache::get_cache_usage,
+            api::cache::trim_cache,
+            api::credentials::is_credentials_locked,
+            api::credentials::unlock_credentials,
+            api::credentials::set_webdav_password,
+            api::account::list_accounts,
+            api::account::switch_account,
+            api::account::logout,
+            api::account::update_account,
+            api::account::connect_local,
+            api::account::connect_database,
+            api::oauth::start_oauth,
+            api::capabilities::get_capabilities,
+            api::search::search,
         ])
         .setup(|app| {
-            let api_path = app.path().app_data_dir().unwrap().join("api_key");
-            let api_key = std::fs::read_to_string(api_path).ok().map(Secret::from);
+            let data_dir = app.path().app_data_dir().unwrap();
+            std::fs::create_dir_all(&data_dir)?;
+            let credentials = CredentialStore::open(&data_dir);
+            let base_directory = std::fs::read_to_string(data_dir.join("base_directory"))
+                .ok()
+                .map(PathBuf::from);
+
+            let transfers =
+                TransferManager::new(app.handle().clone(), data_dir.join("transfers.json"));
+            let resume = transfers.clone();
 
             app.manage(Mutex::new(AppState {
-                api_key,
-                ..Default::default()
+                zotero: None,
+                data: None,
+                api_key: None,
+                credentials,
+                accounts: Accounts::load(&data_dir),
+                base_directory,
+                settings: Settings::load(&data_dir.join("settings.json")),
+                pins: Pins::default(),
+                transfers,
             }));
+            resume.resume_saved();
+
+            let state = app.state::<Mutex<AppState>>();
+            let mut state = state.lock();
+            api::account::migrate_legacy(&mut state, &data_dir);
+            let activated = state.accounts.current.map(|user_id| {
+                api::account::activate(app.handle(), &mut state, user_id)
+                    .inspect_err(|e| tracing::error!("activate account failed: {:?}", e))
+            });
+            if !matches!(activated, Some(Ok(()))) {
+                transfer::watcher::start(app.handle().clone());
+            }
             Ok(())
         })
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app, event| {
+            if let tauri::RunEvent::Resumed = event {
+                transfer::watcher::scan(app.clone());
+            }
+        });
 }
 
 fn init_logger() {
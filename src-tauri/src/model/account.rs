@@ -0,0 +1,169 @@
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::dal::zotero::model::KeyInfo;
+use crate::dal::zotero::{Endpoint, LOCAL_USER_ID};
+use crate::model::auth::UserName;
+
+const ACCOUNTS_FILE: &str = "accounts.json";
+const ACCOUNTS_DIR: &str = "accounts";
+
+/// files in an account's directory
+pub const LIBRARY_FILE: &str = "library.db";
+pub const KEY_INFO_FILE: &str = "key_info.json";
+pub const PINS_FILE: &str = "pins.json";
+pub const SEARCH_FILE: &str = "search.db";
+/// copy of the desktop database of an account read from one
+pub const SNAPSHOT_FILE: &str = "zotero.sqlite";
+
+/// webdav server of an account, without a url and username webdav is not configured
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WebDavSettings {
+    pub url: Option<String>,
+    pub username: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Account {
+    pub user_id: i64,
+    pub user_name: UserName,
+    #[serde(default)]
+    pub endpoint: Endpoint,
+    #[serde(default)]
+    pub webdav: WebDavSettings,
+    /// where downloaded attachments are kept, [`crate::storage::DOCUMENT_PATH`] when unset
+    #[serde(default)]
+    pub storage_root: Option<PathBuf>,
+    /// `zotero.sqlite` of zotero desktop the library is read from, instead of an api
+    #[serde(default)]
+    pub database: Option<PathBuf>,
+}
+
+impl Account {
+    /// the library of zotero desktop, read through its local api or from its
+    /// database. it is read only and its files belong to the desktop.
+    pub fn is_local(&self) -> bool {
+        self.user_id == LOCAL_USER_ID || self.database.is_some()
+    }
+}
+
+/// every account logged in on this device, saved as json in the app data dir.
+///
+/// each account has its own directory for the library, key info and pins.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Accounts {
+    pub accounts: Vec<Account>,
+    pub current: Option<i64>,
+    #[serde(skip)]
+    data_dir: PathBuf,
+}
+
+impl Accounts {
+    pub fn load(data_dir: &Path) -> Self {
+        let mut accounts: Self = std::fs::read_to_string(data_dir.join(ACCOUNTS_FILE))
+            .ok()
+            .and_then(|x| serde_json::from_str(&x).ok())
+            .unwrap_or_default();
+        accounts.data_dir = data_dir.to_path_buf();
+        accounts
+    }
+
+    pub fn save(&self) -> anyhow::Result<()> {
+        std::fs::write(
+            self.data_dir.join(ACCOUNTS_FILE),
+            serde_json::to_string_pretty(self)?,
+        )?;
+        Ok(())
+    }
+
+    pub fn get(&self, user_id: i64) -> Option<&Account> {
+        self.accounts.iter().find(|x| x.user_id == user_id)
+    }
+
+    pub fn get_mut(&mut self, user_id: i64) -> Option<&mut Account> {
+        self.accounts.iter_mut().find(|x| x.user_id == user_id)
+    }
+
+    pub fn current(&self) -> Option<&Account> {
+        self.current.and_then(|x| self.get(x))
+    }
+
+    /// add the account of `info`, or update the user name and endpoint of a known one
+    pub fn add(&mut self, info: &KeyInfo, endpoint: &Endpoint) {
+        match self.get_mut(info.user_id) {
+            Some(account) => {
+                account.user_name = info.user_name.clone();
+                account.endpoint = endpoint.clone();
+            }
+            None => self.accounts.push(Account {
+                user_id: info.user_id,
+                user_name: info.user_name.clone(),
+                endpoint: endpoint.clone(),
+                webdav: WebDavSettings::default(),
+                storage_root: None,
+                database: None,
+            }),
+        }
+    }
+
+    pub fn remove(&mut self, user_id: i64) {
+        self.accounts.retain(|x| x.user_id != user_id);
+        if self.current == Some(user_id) {
+            self.current = None;
+        }
+    }
+
+    /// data dir of one account, created when missing
+    pub fn dir(&self, user_id: i64) -> PathBuf {
+        let dir = self.data_dir.join(ACCOUNTS_DIR).join(user_id.to_string());
+        if let Err(e) = std::fs::create_dir_all(&dir) {
+            tracing::error!("create {:?} failed: {:?}", dir, e);
+        }
+        dir
+    }
+
+    pub fn current_dir(&self) -> Option<PathBuf> {
+        self.current.map(|x| self.dir(x))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dal::zotero::model::Access;
+
+    fn info(user_id: i64, user_name: &str) -> KeyInfo {
+        KeyInfo {
+            user_id,
+            user_name: user_name.to_string(),
+            access: Access::default(),
+        }
+    }
+
+    #[test]
+    fn test_accounts() {
+        let dir = std::env::temp_dir().join("zotero-accounts");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let mut accounts = Accounts::load(&dir);
+        let endpoint = Endpoint::default();
+        accounts.add(&info(1, "a"), &endpoint);
+        accounts.add(&info(2, "b"), &endpoint);
+        accounts.add(&info(1, "a2"), &endpoint);
+        accounts.current = Some(1);
+        accounts.save().unwrap();
+
+        let mut accounts = Accounts::load(&dir);
+        assert_eq!(accounts.accounts.len(), 2);
+        assert_eq!(accounts.current().unwrap().user_name, "a2");
+        assert!(accounts.current_dir().unwrap().ends_with("accounts/1"));
+
+        accounts.remove(1);
+        assert_eq!(accounts.current, None);
+        assert_eq!(accounts.accounts.len(), 1);
+    }
+}
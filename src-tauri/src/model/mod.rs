@@ -1,2 +1,4 @@
+pub mod account;
 pub mod auth;
+pub mod settings;
 pub mod zotero_data;
@@ -0,0 +1,37 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::storage::extract::ExtractLimits;
+
+/// user adjustable options, saved as json in the app data dir
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub extract_limits: ExtractLimits,
+    /// bytes, downloaded attachments are evicted above this, 0 means no limit
+    pub cache_quota: u64,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            extract_limits: ExtractLimits::default(),
+            cache_quota: 2 * 1024 * 1024 * 1024,
+        }
+    }
+}
+
+impl Settings {
+    pub fn load(path: &Path) -> Self {
+        std::fs::read_to_string(path)
+            .ok()
+            .and_then(|x| serde_json::from_str(&x).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
@@ -1,16 +1,37 @@
-use ahash::AHashMap;
-use serde::Serialize;
+use ahash::{AHashMap, AHashSet};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
 use std::sync::Arc;
+use tracing::warn;
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
 
-use crate::dal::zotero::api::item::model::Item;
-#[derive(Debug, Serialize)]
+use crate::dal::zotero::api::collection::model::Collection;
+use crate::dal::zotero::api::item::model::{Item, LinkMode};
+/// not serialized itself, a deep tree would overflow the stack. it is handed out
+/// as [`CollectionNode`]s instead.
+#[derive(Debug)]
 pub struct CollectionsData {
     pub name: String,
     pub key: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
     pub children: Option<Vec<CollectionsData>>,
+    pub father: Option<String>,
+    /// top level items directly in the collection
+    pub item_count: usize,
+    /// top level items in the collection and its subcollections, each counted once
+    pub total_count: usize,
+}
+
+/// a collection without its subcollections, `father` links it into the tree
+#[derive(Debug, Serialize)]
+pub struct CollectionNode {
+    pub name: String,
+    pub key: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub father: Option<String>,
+    pub item_count: usize,
+    pub total_count: usize,
 }
 
 #[derive(Debug, Serialize)]
@@ -19,12 +40,713 @@ pub struct SimpleItemData {
     pub title: String,
 }
 
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortKey {
+    #[default]
+    Title,
+    /// the creator summary, `Smith et al.`
+    Creator,
+    Year,
+    DateAdded,
+    DateModified,
+    ItemType,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+/// position of an item in a listing, also the cursor handed out for the next page
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SortEntry {
+    /// lowercased value of the sort key, empty when the item has none
+    pub value: String,
+    pub title: String,
+    pub key: String,
+}
+
+impl SortEntry {
+    fn new(item: &Item, sort: SortKey) -> Self {
+        let title = item.data.title.clone().unwrap_or_default().to_lowercase();
+        let value = match sort {
+            SortKey::Title => title.clone(),
+            SortKey::Creator => item.creator_summary().to_lowercase(),
+            SortKey::Year => item.year().map(|x| format!("{:04}", x)).unwrap_or_default(),
+            SortKey::DateAdded => item.data.date_added.clone(),
+            SortKey::DateModified => item.data.date_modified.clone(),
+            SortKey::ItemType => item.data.item_type.clone(),
+        };
+        Self {
+            value,
+            title,
+            key: item.key.clone(),
+        }
+    }
+
+    /// items without a value come last in both orders, ties are broken by title and key
+    pub fn cmp(&self, other: &Self, order: SortOrder) -> Ordering {
+        let value = match order {
+            SortOrder::Asc => self.value.cmp(&other.value),
+            SortOrder::Desc => other.value.cmp(&self.value),
+        };
+        self.value
+            .is_empty()
+            .cmp(&other.value.is_empty())
+            .then(value)
+            .then_with(|| self.title.cmp(&other.title))
+            .then_with(|| self.key.cmp(&other.key))
+    }
+}
+
+/// collection key, whether subcollections are included, sort key and order of a listing
+pub type ListingId = (String, bool, SortKey, SortOrder);
+
+/// one line of the item list
+#[derive(Debug, Serialize)]
+pub struct ItemRow {
+    pub key: String,
+    pub title: String,
+    pub creator_summary: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub year: Option<i32>,
+    pub item_type: String,
+    pub attachments: usize,
+    pub notes: usize,
+    /// state of the pdf, or of the first attachment when there is no pdf
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub local_state: Option<LocalFileState>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ItemPage {
+    pub rows: Vec<ItemRow>,
+    /// items in the whole listing
+    pub total: usize,
+    /// pass back to get the next page, `None` on the last one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LocalFileState {
+    NotDownloaded,
+    Downloaded,
+    /// linked file which can not be found on this device
+    Missing,
+    /// linked url, there is no file to download
+    Link,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AttachmentData {
+    pub key: String,
+    pub title: String,
+    pub content_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filename: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub link_mode: Option<LinkMode>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub remote_size: Option<i64>,
+    pub local_state: LocalFileState,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FieldData {
+    /// name in the api, such as `publicationTitle`
+    pub field: String,
+    pub label: String,
+    pub value: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CreatorData {
+    pub creator_type: String,
+    /// label of the creator type
+    pub role: String,
+    pub first_name: String,
+    pub last_name: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TagData {
+    pub tag: String,
+    /// added by zotero, not by the user
+    pub automatic: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CollectionPath {
+    pub key: String,
+    /// names from the top level collection down to this one
+    pub path: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct NoteData {
+    pub key: String,
+    pub title: String,
+    /// html
+    pub note: String,
+    pub date_modified: String,
+}
+
+/// everything shown about one item
+#[derive(Debug, Serialize)]
+pub struct ItemDetail {
+    pub key: String,
+    pub item_type: String,
+    pub item_type_label: String,
+    pub title: String,
+    /// non-empty fields in display order, the title included
+    pub fields: Vec<FieldData>,
+    pub creators: Vec<CreatorData>,
+    pub tags: Vec<TagData>,
+    pub collections: Vec<CollectionPath>,
+    pub attachments: Vec<AttachmentData>,
+    pub notes: Vec<NoteData>,
+    /// related items which are in the library
+    pub related: Vec<SimpleItemData>,
+    pub date_added: String,
+    pub date_modified: String,
+}
+
 pub struct Data {
     pub collections: Arc<Vec<CollectionsData>>,
     pub items: Arc<AHashMap<String, Item>>,
 
     /// empty key means the item is not in any collection
     pub collections_item_map: Arc<AHashMap<String, Arc<Vec<SimpleItemData>>>>,
+
+    /// sorted listings handed out so far, built on first use
+    pub listings: Mutex<AHashMap<ListingId, Arc<Vec<SortEntry>>>>,
+}
+
+impl Drop for CollectionsData {
+    /// nested collections are dropped one level at a time, not recursively, so a
+    /// deep tree can not overflow the stack
+    fn drop(&mut self) {
+        let mut stack = self.children.take().unwrap_or_default();
+        while let Some(mut collection) = stack.pop() {
+            stack.extend(collection.children.take().into_iter().flatten());
+        }
+    }
+}
+
+/// where a collection ends up in the tree
+#[derive(Debug, Clone, Copy)]
+enum Place {
+    Root,
+    Under(usize),
+    Unresolved,
+}
+
+/// part of a name as it is compared, numbers by their value
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum NameChunk {
+    /// digits without leading zeros, a longer number is the larger one
+    Number(usize, String),
+    Text(String),
+}
+
+/// sort key of a name the way zotero orders collections: case and accents are
+/// ignored and numbers compare by value, so `Part 2` comes before `Part 10`
+fn natural_key(name: &str) -> Vec<NameChunk> {
+    let folded: String = name
+        .nfd()
+        .filter(|x| !is_combining_mark(*x))
+        .flat_map(char::to_lowercase)
+        .collect();
+    let mut chunks = vec![];
+    let mut rest = folded.as_str();
+    while let Some(first) = rest.chars().next() {
+        let digits = first.is_ascii_digit();
+        let end = rest
+            .find(|x: char| x.is_ascii_digit() != digits)
+            .unwrap_or(rest.len());
+        let chunk = &rest[..end];
+        chunks.push(if digits {
+            let number = chunk.trim_start_matches('0');
+            NameChunk::Number(number.len(), number.to_string())
+        } else {
+            NameChunk::Text(chunk.to_string())
+        });
+        rest = &rest[end..];
+    }
+    chunks
+}
+
+/// place `start` and, breadth first, all of its descendants which are not placed
+/// yet. a parent always comes before its children in `order`.
+fn place_subtree(
+    children: &[Vec<usize>],
+    places: &mut [Option<Place>],
+    order: &mut Vec<usize>,
+    start: usize,
+    place: Place,
+) {
+    places[start] = Some(place);
+    let mut next = order.len();
+    order.push(start);
+    while let Some(&i) = order.get(next) {
+        next += 1;
+        for &child in &children[i] {
+            if places[child].is_none() {
+                places[child] = Some(Place::Under(i));
+                order.push(child);
+            }
+        }
+    }
+}
+
+fn sort_by_name(collections: &mut [CollectionsData]) {
+    collections.sort_by_cached_key(|x| (natural_key(&x.name), x.name.clone(), x.key.clone()));
+}
+
+impl CollectionsData {
+    /// the tree of `collections`, sorted by name on every level and with the item
+    /// counts of `items`. collections whose parent is missing or whose parents
+    /// form a cycle are put under the [`UNRESOLVED_COLLECTION_KEY`] node.
+    pub fn tree(
+        collections: Vec<Collection>,
+        items: &AHashMap<String, Arc<Vec<SimpleItemData>>>,
+    ) -> Vec<CollectionsData> {
+        let index: AHashMap<&str, usize> = collections
+            .iter()
+            .enumerate()
+            .map(|(i, x)| (x.data.key.as_str(), i))
+            .collect();
+        let parents: Vec<Option<usize>> = collections
+            .iter()
+            .map(|x| {
+                let parent = x.data.parent_collection.as_deref()?;
+                index.get(parent).copied()
+            })
+            .collect();
+        let mut children = vec![vec![]; collections.len()];
+        for (i, parent) in parents.iter().enumerate() {
+            if let Some(parent) = parent {
+                children[*parent].push(i);
+            }
+        }
+
+        let mut places: Vec<Option<Place>> = vec![None; collections.len()];
+        let mut order = Vec::with_capacity(collections.len());
+        for (i, collection) in collections.iter().enumerate() {
+            if collection.data.parent_collection.is_none() {
+                place_subtree(&children, &mut places, &mut order, i, Place::Root);
+            }
+        }
+        for (i, collection) in collections.iter().enumerate() {
+            if collection.data.parent_collection.is_some() && parents[i].is_none() {
+                warn!(
+                    "parent of collection {} not found: {:?}",
+                    collection.data.key, collection.data.parent_collection
+                );
+                place_subtree(&children, &mut places, &mut order, i, Place::Unresolved);
+            }
+        }
+        // what is left hangs off a cycle, which is broken at its smallest key
+        let mut rest: Vec<usize> = (0..collections.len())
+            .filter(|x| places[*x].is_none())
+            .collect();
+        rest.sort_by(|a, b| collections[*a].data.key.cmp(&collections[*b].data.key));
+        for i in rest {
+            if places[i].is_some() {
+                continue;
+            }
+            // every parent is there and unplaced, so the walk ends up in the cycle
+            let mut chain = vec![];
+            let mut seen = AHashSet::new();
+            let mut current = i;
+            while seen.insert(current) {
+                chain.push(current);
+                current = parents[current].unwrap_or(current);
+            }
+            let cycle = &chain[chain.iter().position(|x| *x == current).unwrap_or(0)..];
+            let start = cycle
+                .iter()
+                .copied()
+                .min_by(|a, b| collections[*a].data.key.cmp(&collections[*b].data.key))
+                .unwrap_or(i);
+            warn!(
+                "collection {} is part of a cycle",
+                collections[start].data.key
+            );
+            place_subtree(&children, &mut places, &mut order, start, Place::Unresolved);
+        }
+
+        // children are built before their parents
+        let keys: Vec<String> = collections.iter().map(|x| x.data.key.clone()).collect();
+        let mut nodes: Vec<Option<Collection>> = collections.into_iter().map(Some).collect();
+        let mut pending: Vec<Vec<CollectionsData>> = (0..nodes.len()).map(|_| vec![]).collect();
+        let mut roots = vec![];
+        let mut unresolved = vec![];
+        for &i in order.iter().rev() {
+            let Some(collection) = nodes[i].take() else {
+                continue;
+            };
+            let mut children = std::mem::take(&mut pending[i]);
+            sort_by_name(&mut children);
+            let place = places[i].unwrap_or(Place::Unresolved);
+            let node = CollectionsData {
+                name: collection.data.name,
+                key: collection.data.key,
+                children: (!children.is_empty()).then_some(children),
+                father: match place {
+                    Place::Root => None,
+                    Place::Under(parent) => Some(keys[parent].clone()),
+                    Place::Unresolved => Some(UNRESOLVED_COLLECTION_KEY.to_string()),
+                },
+                item_count: 0,
+                total_count: 0,
+            };
+            match place {
+                Place::Root => roots.push(node),
+                Place::Under(parent) => pending[parent].push(node),
+                Place::Unresolved => unresolved.push(node),
+            }
+        }
+        sort_by_name(&mut roots);
+        if !unresolved.is_empty() {
+            sort_by_name(&mut unresolved);
+            roots.push(CollectionsData {
+                name: UNRESOLVED_COLLECTION_NAME.to_string(),
+                key: UNRESOLVED_COLLECTION_KEY.to_string(),
+                children: Some(unresolved),
+                father: None,
+                item_count: 0,
+                total_count: 0,
+            });
+        }
+        Self::count_items(&mut roots, items);
+        roots
+    }
+
+    fn find<'a>(collections: &'a [CollectionsData], key: &str) -> Option<&'a CollectionsData> {
+        let mut stack: Vec<&CollectionsData> = collections.iter().collect();
+        while let Some(collection) = stack.pop() {
+            if collection.key == key {
+                return Some(collection);
+            }
+            stack.extend(collection.children.iter().flatten());
+        }
+        None
+    }
+
+    /// set [`CollectionsData::item_count`] and [`CollectionsData::total_count`] of
+    /// every collection in the tree from the direct members in `items`
+    pub fn count_items(
+        collections: &mut [CollectionsData],
+        items: &AHashMap<String, Arc<Vec<SimpleItemData>>>,
+    ) {
+        // parents come before their children, so walking it backwards every
+        // collection is visited after its subcollections
+        let mut order = vec![];
+        let mut stack: Vec<&CollectionsData> = collections.iter().collect();
+        while let Some(collection) = stack.pop() {
+            order.push(collection);
+            stack.extend(collection.children.iter().flatten());
+        }
+        let mut members: AHashMap<&str, AHashSet<&str>> = AHashMap::new();
+        let mut counts: AHashMap<String, (usize, usize)> = AHashMap::new();
+        for collection in order.into_iter().rev() {
+            let direct = items
+                .get(&collection.key)
+                .map(|x| x.as_slice())
+                .unwrap_or_default();
+            let mut keys: AHashSet<&str> = direct.iter().map(|x| x.key.as_str()).collect();
+            for child in collection.children.iter().flatten() {
+                keys.extend(members.remove(child.key.as_str()).unwrap_or_default());
+            }
+            counts.insert(collection.key.clone(), (direct.len(), keys.len()));
+            members.insert(&collection.key, keys);
+        }
+
+        let mut stack: Vec<&mut CollectionsData> = collections.iter_mut().collect();
+        while let Some(collection) = stack.pop() {
+            let (direct, total) = counts.get(&collection.key).copied().unwrap_or_default();
+            collection.item_count = direct;
+            collection.total_count = total;
+            stack.extend(collection.children.iter_mut().flatten());
+        }
+    }
+
+    /// keys of this collection and all of its descendants
+    fn keys(&self) -> Vec<String> {
+        let mut keys = vec![];
+        let mut stack = vec![self];
+        while let Some(collection) = stack.pop() {
+            keys.push(collection.key.clone());
+            stack.extend(collection.children.iter().flatten());
+        }
+        keys
+    }
+
+    /// every collection of the tree, parents before their children and siblings
+    /// in their order
+    pub fn flatten(collections: &[CollectionsData]) -> Vec<CollectionNode> {
+        let mut nodes = vec![];
+        let mut stack: Vec<&CollectionsData> = collections.iter().rev().collect();
+        while let Some(collection) = stack.pop() {
+            nodes.push(CollectionNode {
+                name: collection.name.clone(),
+                key: collection.key.clone(),
+                father: collection.father.clone(),
+                item_count: collection.item_count,
+                total_count: collection.total_count,
+            });
+            stack.extend(collection.children.iter().flatten().rev());
+        }
+        nodes
+    }
+}
+
+impl Data {
+    pub fn find_attachment(&self, key: &str) -> Option<&Item> {
+        self.items
+            .values()
+            .flat_map(|x| x.attachments())
+            .find(|x| x.key == key)
+    }
+
+    pub fn find_collection(&self, key: &str) -> Option<&CollectionsData> {
+        CollectionsData::find(&self.collections, key)
+    }
+
+    /// `key` itself, and with `recursive` the keys of all its subcollections
+    pub fn collection_keys(&self, key: &str, recursive: bool) -> Vec<String> {
+        match self.find_collection(key) {
+            Some(collection) if recursive => collection.keys(),
+            _ => vec![key.to_string()],
+        }
+    }
+
+    /// `key` and its ancestors, the top level collection first
+    pub fn collection_path(&self, key: &str) -> Option<CollectionPath> {
+        let mut path = vec![];
+        let mut seen = AHashSet::new();
+        let mut next = Some(key.to_string());
+        while let Some(key) = next {
+            // a broken parent chain must not loop forever
+            if !seen.insert(key.clone()) {
+                break;
+            }
+            let collection = self.find_collection(&key)?;
+            path.push(collection.name.clone());
+            next = collection.father.clone();
+        }
+        path.reverse();
+        Some(CollectionPath {
+            key: key.to_string(),
+            path,
+        })
+    }
+
+    /// top level items of `collection`, with `recursive` also those of its
+    /// subcollections, or of the whole library for [`ALL_ITEMS_KEY`], sorted. the
+    /// listing is kept until the data is replaced by a refresh.
+    pub fn listing(
+        &self,
+        collection: &str,
+        recursive: bool,
+        sort: SortKey,
+        order: SortOrder,
+    ) -> Arc<Vec<SortEntry>> {
+        let id = (collection.to_string(), recursive, sort, order);
+        if let Some(listing) = self.listings.lock().get(&id) {
+            return listing.clone();
+        }
+        let mut entries: Vec<SortEntry> = if collection == ALL_ITEMS_KEY {
+            self.items
+                .values()
+                .map(|x| SortEntry::new(x, sort))
+                .collect()
+        } else {
+            self.collection_items(&self.collection_keys(collection, recursive))
+                .into_iter()
+                .map(|x| SortEntry::new(x, sort))
+                .collect()
+        };
+        entries.sort_by(|a, b| a.cmp(b, order));
+        let listing = Arc::new(entries);
+        self.listings.lock().insert(id, listing.clone());
+        listing
+    }
+
+    /// top level items of the collections, each item only once
+    pub fn collection_items<'a>(&'a self, keys: &[String]) -> Vec<&'a Item> {
+        let mut seen = AHashSet::new();
+        keys.iter()
+            .filter_map(|x| self.collections_item_map.get(x))
+            .flat_map(|x| x.iter())
+            .filter(|x| seen.insert(x.key.as_str()))
+            .filter_map(|x| self.items.get(&x.key))
+            .collect()
+    }
 }
 
 pub const EMPTY_COLLECTION_KEY: &str = "";
+/// lists every top level item of the library
+pub const ALL_ITEMS_KEY: &str = "all-items";
+/// the node holding collections whose parent can not be found, it comes last
+pub const UNRESOLVED_COLLECTION_KEY: &str = "unresolved-collections";
+const UNRESOLVED_COLLECTION_NAME: &str = "Unresolved";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn collection(key: &str, children: Vec<CollectionsData>) -> CollectionsData {
+        CollectionsData {
+            name: key.to_lowercase(),
+            key: key.to_string(),
+            children: (!children.is_empty()).then_some(children),
+            father: None,
+            item_count: 0,
+            total_count: 0,
+        }
+    }
+
+    fn source(key: &str, name: &str, parent: Option<&str>) -> Collection {
+        let mut collection = Collection {
+            key: key.to_string(),
+            ..Default::default()
+        };
+        collection.data.key = key.to_string();
+        collection.data.name = name.to_string();
+        collection.data.parent_collection = parent.map(|x| x.to_string());
+        collection
+    }
+
+    fn names(collections: &[CollectionsData]) -> Vec<&str> {
+        collections.iter().map(|x| x.name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_tree() {
+        let collections = vec![
+            source("P10", "Part 10", None),
+            source("P2", "part 2", None),
+            source("E", "Évian", Some("P2")),
+            source("D", "delta", Some("P2")),
+            source("ORPHAN", "orphan", Some("GONE")),
+            source("UNDER", "under orphan", Some("ORPHAN")),
+            // X and Y are each other's parent, Z hangs off the cycle
+            source("X", "x", Some("Y")),
+            source("Y", "y", Some("X")),
+            source("Z", "z", Some("Y")),
+        ];
+        let tree = CollectionsData::tree(collections, &AHashMap::new());
+
+        assert_eq!(names(&tree), ["part 2", "Part 10", "Unresolved"]);
+        assert_eq!(
+            names(tree[0].children.as_ref().unwrap()),
+            ["delta", "Évian"]
+        );
+        let unresolved = tree[2].children.as_ref().unwrap();
+        assert_eq!(names(unresolved), ["orphan", "x"]);
+        assert_eq!(unresolved[0].children.as_ref().unwrap()[0].key, "UNDER");
+        assert_eq!(
+            unresolved[1].father.as_deref(),
+            Some(UNRESOLVED_COLLECTION_KEY)
+        );
+        let y = &unresolved[1].children.as_ref().unwrap()[0];
+        assert_eq!(y.key, "Y");
+        assert_eq!(y.children.as_ref().unwrap()[0].key, "Z");
+        let keys: Vec<_> = CollectionsData::flatten(&tree)
+            .into_iter()
+            .map(|x| x.key)
+            .collect();
+        assert_eq!(
+            keys,
+            [
+                "P2",
+                "D",
+                "E",
+                "P10",
+                UNRESOLVED_COLLECTION_KEY,
+                "ORPHAN",
+                "UNDER",
+                "X",
+                "Y",
+                "Z"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_deep_tree() {
+        let depth = 100_000;
+        let collections = (0..depth)
+            .map(|i| {
+                let parent = (i > 0).then(|| (i - 1).to_string());
+                source(&i.to_string(), "c", parent.as_deref())
+            })
+            .collect();
+        let items = [(
+            (depth - 1).to_string(),
+            Arc::new(vec![SimpleItemData {
+                key: "ITEM".to_string(),
+                title: String::new(),
+            }]),
+        )]
+        .into_iter()
+        .collect();
+        let tree = CollectionsData::tree(collections, &items);
+        assert_eq!(tree.len(), 1);
+        assert_eq!(tree[0].total_count, 1);
+        let keys = tree[0].keys();
+        assert_eq!(keys.len(), depth);
+
+        let nodes = CollectionsData::flatten(&tree);
+        assert_eq!(nodes.len(), depth);
+        assert_eq!(nodes[1].father.as_deref(), Some("0"));
+        let json = serde_json::to_string(&nodes).unwrap();
+        assert!(json.contains(r#""key":"99999","father":"99998""#));
+    }
+
+    #[test]
+    fn test_count_items() {
+        let mut collections = vec![collection(
+            "ROOT",
+            vec![
+                collection("A", vec![collection("B", vec![])]),
+                collection("C", vec![]),
+            ],
+        )];
+        let items: AHashMap<String, Arc<Vec<SimpleItemData>>> = [
+            ("ROOT", vec!["1"]),
+            ("A", vec!["2", "3"]),
+            // an item in several subcollections is counted once
+            ("B", vec!["3", "4"]),
+            ("C", vec!["1"]),
+        ]
+        .into_iter()
+        .map(|(collection, keys)| {
+            let items = keys
+                .into_iter()
+                .map(|x| SimpleItemData {
+                    key: x.to_string(),
+                    title: String::new(),
+                })
+                .collect();
+            (collection.to_string(), Arc::new(items))
+        })
+        .collect();
+        CollectionsData::count_items(&mut collections, &items);
+
+        let root = &collections[0];
+        assert_eq!((root.item_count, root.total_count), (1, 4));
+        let a = &root.children.as_ref().unwrap()[0];
+        assert_eq!((a.item_count, a.total_count), (2, 3));
+        let b = &a.children.as_ref().unwrap()[0];
+        assert_eq!((b.item_count, b.total_count), (2, 2));
+    }
+}
@@ -0,0 +1,227 @@
+use std::fs::{self, File};
+use std::io;
+use std::path::Path;
+
+use ahash::{AHashMap, AHashSet};
+use md5::{Digest, Md5};
+use parking_lot::Mutex;
+use serde::Serialize;
+use tauri::{AppHandle, Manager};
+use tracing::{debug, info, warn};
+
+use super::sync_state::SyncState;
+use super::{attachment_dir, file_path};
+use crate::AppState;
+
+/// written into the attachment's directory every time the file is opened
+const OPENED_FILE: &str = ".opened";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CacheEntry {
+    pub key: String,
+    /// bytes, everything in the attachment's directory
+    pub size: u64,
+    /// last time the file was opened in ms, or when it was downloaded
+    pub opened: i64,
+}
+
+/// remember that the attachment was just opened
+pub fn touch(key: &str) {
+    let now = chrono::Utc::now().timestamp_millis();
+    if let Err(e) = fs::write(attachment_dir(key).join(OPENED_FILE), now.to_string()) {
+        warn!("save open time of {} failed: {:?}", key, e);
+    }
+}
+
+/// every downloaded attachment directory
+pub fn entries() -> Vec<CacheEntry> {
+    let Ok(dirs) = fs::read_dir(super::root()) else {
+        return vec![];
+    };
+    dirs.flatten()
+        .filter(|x| x.path().is_dir())
+        .filter_map(|x| {
+            let key = x.file_name().to_str()?.to_string();
+            if key.starts_with('.') {
+                return None;
+            }
+            let opened = fs::read_to_string(x.path().join(OPENED_FILE))
+                .ok()
+                .and_then(|x| x.trim().parse().ok())
+                .or_else(|| {
+                    let modified = x.metadata().ok()?.modified().ok()?;
+                    Some(chrono::DateTime::<chrono::Utc>::from(modified).timestamp_millis())
+                })
+                .unwrap_or_default();
+            Some(CacheEntry {
+                size: dir_size(&x.path()),
+                key,
+                opened,
+            })
+        })
+        .collect()
+}
+
+fn dir_size(path: &Path) -> u64 {
+    let mut size = 0;
+    let mut stack = vec![path.to_path_buf()];
+    while let Some(dir) = stack.pop() {
+        for entry in fs::read_dir(dir).into_iter().flatten().flatten() {
+            match entry.metadata() {
+                Ok(meta) if meta.is_dir() => stack.push(entry.path()),
+                Ok(meta) => size += meta.len(),
+                Err(_) => {}
+            }
+        }
+    }
+    size
+}
+
+/// the file differs from what was last synced, or it was never synced at all
+pub fn has_local_changes(key: &str, filename: &str) -> bool {
+    let path = file_path(key, filename);
+    if !path.exists() {
+        return false;
+    }
+    let Some(synced) = SyncState::load(key) else {
+        return true;
+    };
+    match md5_file(&path) {
+        Ok(hash) => hash != synced.hash,
+        Err(e) => {
+            warn!("hash {:?} failed: {:?}", path, e);
+            true
+        }
+    }
+}
+
+fn md5_file(path: &Path) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut h = Md5::new();
+    io::copy(&mut file, &mut h)?;
+    Ok(hex::encode(h.finalize()))
+}
+
+/// least recently opened entries to remove until the rest fits into `quota`,
+/// entries rejected by `evictable` are always kept
+fn plan_eviction(
+    mut entries: Vec<CacheEntry>,
+    quota: u64,
+    mut evictable: impl FnMut(&CacheEntry) -> bool,
+) -> Vec<CacheEntry> {
+    let mut total: u64 = entries.iter().map(|x| x.size).sum();
+    entries.sort_by_key(|x| x.opened);
+
+    let mut evicted = vec![];
+    for entry in entries {
+        if total <= quota {
+            break;
+        }
+        if evictable(&entry) {
+            total -= entry.size;
+            evicted.push(entry);
+        }
+    }
+    evicted
+}
+
+/// remove least recently used attachments until the cache fits into the quota
+/// from the settings, returns the freed bytes.
+///
+/// pinned attachments, running transfers and files with local changes which are
+/// not uploaded yet are never removed. neither are directories of attachments
+/// unknown to the current data, since their files can not be checked, or
+/// `finished`, the attachment whose download just completed.
+pub async fn trim(app: &AppHandle, finished: Option<&str>) -> anyhow::Result<u64> {
+    let (quota, keep, files) = {
+        let state = app.state::<Mutex<AppState>>();
+        let state = state.lock();
+        let Some(data) = &state.data else {
+            return Ok(0);
+        };
+        // the storage directory of zotero desktop is not a cache
+        if state.accounts.current().is_some_and(|x| x.is_local()) {
+            return Ok(0);
+        }
+        let quota = state.settings.cache_quota;
+        let mut keep: AHashSet<String> = state.pins.pinned_keys(data);
+        keep.extend(
+            state
+                .transfers
+                .list()
+                .into_iter()
+                .filter(|x| !x.status.is_finished())
+                .map(|x| x.key),
+        );
+        keep.extend(finished.map(str::to_string));
+        let files: AHashMap<String, String> = data
+            .items
+            .values()
+            .flat_map(|x| x.attachments())
+            .filter(|x| x.is_stored_file())
+            .filter_map(|x| Some((x.key.clone(), x.data.filename.clone()?)))
+            .collect();
+        (quota, keep, files)
+    };
+    if quota == 0 {
+        return Ok(0);
+    }
+
+    tokio::task::spawn_blocking(move || -> anyhow::Result<u64> {
+        let evicted = plan_eviction(entries(), quota, |entry| {
+            if keep.contains(&entry.key) {
+                return false;
+            }
+            match files.get(&entry.key) {
+                Some(filename) if has_local_changes(&entry.key, filename) => {
+                    debug!("{} has local changes, keep it", entry.key);
+                    false
+                }
+                Some(_) => true,
+                None => false,
+            }
+        });
+
+        let mut freed = 0;
+        for entry in evicted {
+            info!("evict {}, {} bytes", entry.key, entry.size);
+            fs::remove_dir_all(attachment_dir(&entry.key))?;
+            freed += entry.size;
+        }
+        Ok(freed)
+    })
+    .await?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(key: &str, size: u64, opened: i64) -> CacheEntry {
+        CacheEntry {
+            key: key.to_string(),
+            size,
+            opened,
+        }
+    }
+
+    #[test]
+    fn test_plan_eviction() {
+        let entries = vec![
+            entry("new", 10, 3),
+            entry("old", 10, 1),
+            entry("pinned", 10, 0),
+        ];
+
+        let evicted = plan_eviction(entries.clone(), 30, |_| true);
+        assert!(evicted.is_empty());
+
+        let evicted = plan_eviction(entries.clone(), 15, |x| x.key != "pinned");
+        let keys: Vec<_> = evicted.iter().map(|x| x.key.as_str()).collect();
+        assert_eq!(keys, ["old", "new"]);
+
+        let evicted = plan_eviction(entries, 25, |x| x.key != "pinned");
+        let keys: Vec<_> = evicted.iter().map(|x| x.key.as_str()).collect();
+        assert_eq!(keys, ["old"]);
+    }
+}
@@ -0,0 +1,277 @@
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+use zip::result::ZipError;
+use zip::ZipArchive;
+
+/// entries smaller than this are not checked against the compression ratio,
+/// tiny text files compress extremely well
+const RATIO_CHECK_MIN_SIZE: u64 = 1024 * 1024;
+
+const STAGING_DIR: &str = ".extract";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExtractLimits {
+    pub max_entries: usize,
+    /// bytes, sum of all entries
+    pub max_total_size: u64,
+    /// bytes, single entry
+    pub max_entry_size: u64,
+    /// uncompressed / compressed size of an entry
+    pub max_compression_ratio: u64,
+}
+
+impl Default for ExtractLimits {
+    fn default() -> Self {
+        Self {
+            max_entries: 256,
+            max_total_size: 2 * 1024 * 1024 * 1024,
+            max_entry_size: 1024 * 1024 * 1024,
+            max_compression_ratio: 100,
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ExtractError {
+    #[error("archive has {0} entries, more than allowed")]
+    TooManyEntries(usize),
+    #[error("entry {0} is larger than allowed")]
+    EntryTooLarge(String),
+    #[error("archive is larger than allowed when extracted")]
+    TooLarge,
+    #[error("entry {0} has a suspicious compression ratio")]
+    CompressionRatio(String),
+    #[error("entry {0} escapes the attachment directory")]
+    UnsafePath(String),
+    #[error("zip: {0}")]
+    Zip(#[from] ZipError),
+    #[error("io: {0}")]
+    Io(#[from] io::Error),
+}
+
+impl ExtractError {
+    /// the archive breaks one of the rules, as opposed to a plain io or format failure
+    pub fn is_violation(&self) -> bool {
+        !matches!(self, Self::Zip(_) | Self::Io(_))
+    }
+}
+
+/// extract `zip_path` into `dest`, which is the attachment's own directory.
+///
+/// every entry is checked against `limits` before anything is written, and the
+/// content is unpacked into a staging directory first so a rejected archive never
+/// replaces existing files. returns the path of the first pdf.
+pub fn extract(
+    zip_path: &Path,
+    dest: &Path,
+    limits: &ExtractLimits,
+) -> Result<Option<PathBuf>, ExtractError> {
+    let mut archive = ZipArchive::new(BufReader::new(File::open(zip_path)?))?;
+    let entries = check(&mut archive, limits)?;
+
+    let staging = dest.join(STAGING_DIR);
+    if staging.exists() {
+        fs::remove_dir_all(&staging)?;
+    }
+    fs::create_dir_all(&staging)?;
+
+    let result = unpack(&mut archive, &entries, &staging, limits);
+    if result.is_ok() {
+        for (_, name, is_dir) in &entries {
+            if *is_dir {
+                continue;
+            }
+            let out_path = dest.join(name);
+            if let Some(p) = out_path.parent() {
+                fs::create_dir_all(p)?;
+            }
+            fs::rename(staging.join(name), &out_path)?;
+        }
+    }
+    fs::remove_dir_all(&staging)?;
+    result?;
+
+    Ok(entries
+        .iter()
+        .find(|(_, name, is_dir)| !is_dir && name.extension() == Some("pdf".as_ref()))
+        .map(|(_, name, _)| dest.join(name)))
+}
+
+/// validate the metadata of every entry, returns (index, relative path, is dir)
+fn check<R: io::Read + io::Seek>(
+    archive: &mut ZipArchive<R>,
+    limits: &ExtractLimits,
+) -> Result<Vec<(usize, PathBuf, bool)>, ExtractError> {
+    if archive.len() > limits.max_entries {
+        return Err(ExtractError::TooManyEntries(archive.len()));
+    }
+
+    let mut entries = Vec::with_capacity(archive.len());
+    let mut total = 0u64;
+    for i in 0..archive.len() {
+        let file = archive.by_index_raw(i)?;
+        let name = file.name().to_string();
+
+        if file.is_symlink() {
+            return Err(ExtractError::UnsafePath(name));
+        }
+        let path = file
+            .enclosed_name()
+            .ok_or_else(|| ExtractError::UnsafePath(name.clone()))?;
+        // the staging dir and the app's own files, like the sync state, are dot
+        // files next to the content
+        let hidden = path
+            .components()
+            .next()
+            .is_some_and(|x| x.as_os_str().to_string_lossy().starts_with('.'));
+        if hidden {
+            return Err(ExtractError::UnsafePath(name));
+        }
+
+        if file.size() > limits.max_entry_size {
+            return Err(ExtractError::EntryTooLarge(name));
+        }
+        total = total.saturating_add(file.size());
+        if total > limits.max_total_size {
+            return Err(ExtractError::TooLarge);
+        }
+        if file.size() >= RATIO_CHECK_MIN_SIZE
+            && file.size() / file.compressed_size().max(1) > limits.max_compression_ratio
+        {
+            return Err(ExtractError::CompressionRatio(name));
+        }
+
+        debug!("zip entry: {:?}, size: {}", path, file.size());
+        entries.push((i, path, file.is_dir()));
+    }
+    Ok(entries)
+}
+
+/// the declared sizes may lie, so the written bytes are counted again
+fn unpack<R: io::Read + io::Seek>(
+    archive: &mut ZipArchive<R>,
+    entries: &[(usize, PathBuf, bool)],
+    staging: &Path,
+    limits: &ExtractLimits,
+) -> Result<(), ExtractError> {
+    let mut total = 0u64;
+    for (i, name, is_dir) in entries {
+        let out_path = staging.join(name);
+        if *is_dir {
+            fs::create_dir_all(&out_path)?;
+            continue;
+        }
+        if let Some(p) = out_path.parent() {
+            fs::create_dir_all(p)?;
+        }
+
+        let file = archive.by_index(*i)?;
+        let mut out_file = File::create(&out_path)?;
+        let written = io::copy(&mut file.take(limits.max_entry_size + 1), &mut out_file)?;
+        if written > limits.max_entry_size {
+            return Err(ExtractError::EntryTooLarge(name.display().to_string()));
+        }
+        total += written;
+        if total > limits.max_total_size {
+            return Err(ExtractError::TooLarge);
+        }
+    }
+    Ok(())
+}
+
+/// move a rejected archive out of the way so it can be inspected later
+pub fn quarantine(zip_path: &Path, quarantine_dir: &Path) -> io::Result<PathBuf> {
+    fs::create_dir_all(quarantine_dir)?;
+    let name = format!(
+        "{}-{}",
+        chrono::Utc::now().timestamp_millis(),
+        zip_path
+            .file_name()
+            .map(|x| x.to_string_lossy())
+            .unwrap_or_default()
+    );
+    let target = quarantine_dir.join(name);
+    fs::rename(zip_path, &target)?;
+    warn!("archive quarantined: {:?}", target);
+    Ok(target)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    fn write_zip(path: &Path, entries: &[(&str, &[u8])]) {
+        let mut zip = zip::ZipWriter::new(File::create(path).unwrap());
+        let options = zip::write::SimpleFileOptions::default();
+        for (name, data) in entries {
+            zip.start_file(*name, options).unwrap();
+            zip.write_all(data).unwrap();
+        }
+        zip.finish().unwrap();
+    }
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("zotero-extract-{}", name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_extract() {
+        let dir = test_dir("ok");
+        let zip_path = dir.join("a.zip");
+        write_zip(&zip_path, &[("paper.pdf", b"%PDF"), ("img/a.png", b"png")]);
+
+        let dest = dir.join("ABCD1234");
+        let pdf = extract(&zip_path, &dest, &ExtractLimits::default()).unwrap();
+        assert_eq!(pdf, Some(dest.join("paper.pdf")));
+        assert!(dest.join("img/a.png").exists());
+        assert!(!dest.join(STAGING_DIR).exists());
+    }
+
+    #[test]
+    fn test_reject_zip_slip() {
+        let dir = test_dir("slip");
+        let zip_path = dir.join("a.zip");
+        write_zip(&zip_path, &[("../../evil.pdf", b"%PDF")]);
+
+        let dest = dir.join("ABCD1234");
+        let err = extract(&zip_path, &dest, &ExtractLimits::default()).unwrap_err();
+        assert!(matches!(err, ExtractError::UnsafePath(_)));
+        assert!(!dir.join("evil.pdf").exists());
+
+        write_zip(&zip_path, &[("paper.pdf", b"%PDF"), (".sync.json", b"{}")]);
+        let err = extract(&zip_path, &dest, &ExtractLimits::default()).unwrap_err();
+        assert!(matches!(err, ExtractError::UnsafePath(_)));
+        assert!(!dest.join(".sync.json").exists());
+    }
+
+    #[test]
+    fn test_reject_limits() {
+        let dir = test_dir("limits");
+        let zip_path = dir.join("a.zip");
+        let zeros = vec![0u8; 2 * RATIO_CHECK_MIN_SIZE as usize];
+        write_zip(&zip_path, &[("a.pdf", &zeros), ("b.pdf", b"b")]);
+        let dest = dir.join("ABCD1234");
+
+        let limits = ExtractLimits {
+            max_entries: 1,
+            ..Default::default()
+        };
+        let err = extract(&zip_path, &dest, &limits).unwrap_err();
+        assert!(matches!(err, ExtractError::TooManyEntries(2)));
+
+        let err = extract(&zip_path, &dest, &ExtractLimits::default()).unwrap_err();
+        assert!(matches!(err, ExtractError::CompressionRatio(_)));
+        assert!(err.is_violation());
+        assert!(!dest.join("b.pdf").exists());
+    }
+}
@@ -0,0 +1,106 @@
+use std::path::Path;
+
+use tracing::debug;
+
+/// zotero desktop keeps the text it extracted from an attachment in its directory
+const FT_CACHE: &str = ".zotero-ft-cache";
+/// larger files are not read for their text. lopdf keeps the whole document in
+/// memory, which a phone can not spare for large files.
+const MAX_FILE_SIZE: u64 = 32 * 1024 * 1024;
+/// text kept of one attachment, in bytes
+const MAX_TEXT_SIZE: usize = 1024 * 1024;
+
+/// text of the attachment file at `path` to search in, `None` for types that
+/// have no text or files that can not be read
+pub fn extract(path: &Path, content_type: &str) -> Option<String> {
+    let cache = path.parent().map(|x| x.join(FT_CACHE));
+    let text = match cache.and_then(|x| std::fs::read_to_string(x).ok()) {
+        Some(text) => text,
+        None => {
+            if std::fs::metadata(path).ok()?.len() > MAX_FILE_SIZE {
+                debug!("{:?} is too large to extract its text", path);
+                return None;
+            }
+            match content_type {
+                "application/pdf" => pdf_text(path)?,
+                "text/html" | "application/xhtml+xml" => {
+                    html_text(&std::fs::read_to_string(path).ok()?)
+                }
+                x if x.starts_with("text/") => std::fs::read_to_string(path).ok()?,
+                _ => return None,
+            }
+        }
+    };
+    Some(truncate(text, MAX_TEXT_SIZE))
+}
+
+fn pdf_text(path: &Path) -> Option<String> {
+    let document = lopdf::Document::load(path)
+        .inspect_err(|e| debug!("read pdf {:?} failed: {}", path, e))
+        .ok()?;
+    let pages: Vec<u32> = document.get_pages().keys().copied().collect();
+    document
+        .extract_text(&pages)
+        .inspect_err(|e| debug!("extract text of {:?} failed: {}", path, e))
+        .ok()
+}
+
+/// the text of notes and html snapshots, tags and scripts dropped
+pub fn html_text(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        text.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let lower = rest.get(..8).unwrap_or(rest).to_ascii_lowercase();
+        // the content of these is not text
+        let skip_to = ["<script", "<style"]
+            .iter()
+            .find(|x| lower.starts_with(*x))
+            .map(|x| format!("</{}", &x[1..]));
+        let end = match skip_to {
+            Some(close) => rest
+                .to_ascii_lowercase()
+                .find(&close)
+                .and_then(|x| rest[x..].find('>').map(|y| x + y)),
+            None => rest.find('>'),
+        };
+        match end {
+            Some(end) => rest = &rest[end + 1..],
+            None => rest = "",
+        }
+        text.push(' ');
+    }
+    text.push_str(rest);
+    let text = text
+        .replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&");
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn truncate(mut text: String, size: usize) -> String {
+    if text.len() > size {
+        let mut end = size;
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        text.truncate(end);
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_html_text() {
+        let html = "<p>Deep &amp; <b>wide</b></p><script>var x = '<p>';</script><STYLE>p {}</STYLE>\n<p>nets</p>";
+        assert_eq!(html_text(html), "Deep & wide nets");
+        assert_eq!(truncate("aé".to_string(), 2), "a");
+    }
+}
@@ -0,0 +1,148 @@
+use std::path::{Component, Path, PathBuf};
+
+use parking_lot::RwLock;
+
+use crate::dal::zotero::api::item::model::{Item, LinkMode};
+use crate::dal::zotero::error::ZoteroError;
+use crate::model::zotero_data::LocalFileState;
+
+pub mod cache;
+pub mod extract;
+pub mod fulltext;
+pub mod pin;
+pub mod sync_state;
+
+pub const DOCUMENT_PATH: &str = "/storage/emulated/0/Download/zotero";
+/// pdf files used to be extracted here, outside of the attachment's directory
+const LEGACY_DATA_PATH: &str = "/storage/emulated/0/Download/";
+const QUARANTINE_DIR: &str = ".quarantine";
+
+/// prefix zotero desktop uses for linked files relative to the "Linked Attachment Base Directory"
+const BASE_DIRECTORY_PREFIX: &str = "attachments:";
+
+static ROOT: RwLock<Option<PathBuf>> = parking_lot::const_rwlock(None);
+
+/// where the attachments of the current account are stored, [`DOCUMENT_PATH`] by default
+pub fn root() -> PathBuf {
+    ROOT.read()
+        .clone()
+        .unwrap_or_else(|| DOCUMENT_PATH.parse().unwrap())
+}
+
+pub fn set_root(root: Option<PathBuf>) {
+    *ROOT.write() = root;
+}
+
+/// directory holding the extracted content of an attachment's zip
+pub fn attachment_dir(key: impl AsRef<str>) -> PathBuf {
+    root().join(key.as_ref())
+}
+
+/// rejected archives are moved here
+pub fn quarantine_dir() -> PathBuf {
+    root().join(QUARANTINE_DIR)
+}
+
+/// where the main file of a stored attachment lives after download
+pub fn attachment_file_path(item: &Item) -> Option<PathBuf> {
+    item.data
+        .filename
+        .as_ref()
+        .map(|filename| file_path(&item.key, filename))
+}
+
+pub fn file_path(key: impl AsRef<str>, filename: impl AsRef<Path>) -> PathBuf {
+    attachment_dir(key).join(filename)
+}
+
+/// move a file downloaded by an older version into the attachment's directory,
+/// so local changes made to it are not lost
+pub fn migrate_legacy_file(item: &Item) -> std::io::Result<()> {
+    let (Some(filename), Some(path)) = (&item.data.filename, attachment_file_path(item)) else {
+        return Ok(());
+    };
+    let legacy = LEGACY_DATA_PATH.parse::<PathBuf>().unwrap().join(filename);
+    if path.exists() || !legacy.is_file() {
+        return Ok(());
+    }
+    tracing::info!("move legacy file {:?} to {:?}", legacy, path);
+    std::fs::create_dir_all(attachment_dir(&item.key))?;
+    std::fs::rename(legacy, path)
+}
+
+/// resolve the path of a `linked_file` attachment on this device.
+///
+/// relative paths are joined onto `base_dir`, absolute paths are used as they are.
+pub fn linked_file_path(item: &Item, base_dir: Option<&Path>) -> Result<PathBuf, ZoteroError> {
+    let path = item
+        .data
+        .path
+        .as_deref()
+        .ok_or_else(|| ZoteroError::NoAttachment(item.key.clone()))?;
+
+    let Some(relative) = path.strip_prefix(BASE_DIRECTORY_PREFIX) else {
+        return Ok(PathBuf::from(path));
+    };
+
+    let mut resolved = base_dir.ok_or(ZoteroError::NoBaseDirectory)?.to_path_buf();
+    // desktop may be windows, so both separators are accepted
+    for part in relative.split(['/', '\\']) {
+        match Path::new(part).components().next() {
+            None | Some(Component::CurDir) => {}
+            Some(Component::Normal(part)) => resolved.push(part),
+            Some(_) => return Err(ZoteroError::LinkedFileMissing(path.to_string())),
+        }
+    }
+    Ok(resolved)
+}
+
+pub fn local_state(item: &Item, base_dir: Option<&Path>) -> LocalFileState {
+    match item.data.link_mode {
+        Some(LinkMode::LinkedUrl) => LocalFileState::Link,
+        Some(LinkMode::LinkedFile) => match linked_file_path(item, base_dir) {
+            Ok(path) if path.exists() => LocalFileState::Downloaded,
+            _ => LocalFileState::Missing,
+        },
+        _ => match attachment_file_path(item) {
+            Some(path) if path.exists() => LocalFileState::Downloaded,
+            _ => LocalFileState::NotDownloaded,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn linked_item(path: &str) -> Item {
+        let mut item = Item::default();
+        item.data.link_mode = Some(LinkMode::LinkedFile);
+        item.data.path = Some(path.to_string());
+        item
+    }
+
+    #[test]
+    fn test_linked_file_path() {
+        let base = Path::new("/sdcard/papers");
+
+        let item = linked_item("attachments:2024\\smith/paper.pdf");
+        assert_eq!(
+            linked_file_path(&item, Some(base)).unwrap(),
+            base.join("2024").join("smith").join("paper.pdf")
+        );
+
+        assert!(matches!(
+            linked_file_path(&item, None),
+            Err(ZoteroError::NoBaseDirectory)
+        ));
+
+        let item = linked_item("attachments:../secret.pdf");
+        assert!(linked_file_path(&item, Some(base)).is_err());
+
+        let item = linked_item("/home/user/paper.pdf");
+        assert_eq!(
+            linked_file_path(&item, None).unwrap(),
+            PathBuf::from("/home/user/paper.pdf")
+        );
+    }
+}
@@ -0,0 +1,145 @@
+use std::path::Path;
+
+use ahash::AHashSet;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::dal::zotero::api::item::model::Item;
+use crate::model::zotero_data::Data;
+use crate::transfer::{Transfer, TransferKind, TransferManager};
+
+use super::sync_state::SyncState;
+
+/// a collection marked as "available offline"
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Pin {
+    pub collection_key: String,
+    /// include the items of all subcollections
+    pub recursive: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Pins {
+    pub pins: Vec<Pin>,
+}
+
+impl Pins {
+    pub fn load(path: &Path) -> Self {
+        std::fs::read_to_string(path)
+            .ok()
+            .and_then(|x| serde_json::from_str(&x).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        std::fs::write(path, serde_json::to_string(self)?)?;
+        Ok(())
+    }
+
+    pub fn pin(&mut self, collection_key: &str, recursive: bool) {
+        self.unpin(collection_key);
+        self.pins.push(Pin {
+            collection_key: collection_key.to_string(),
+            recursive,
+        });
+    }
+
+    pub fn unpin(&mut self, collection_key: &str) {
+        self.pins.retain(|x| x.collection_key != collection_key);
+    }
+
+    /// stored file attachments of the items in a pinned collection
+    pub fn attachments<'a>(pin: &Pin, data: &'a Data) -> Vec<&'a Item> {
+        let keys = data.collection_keys(&pin.collection_key, pin.recursive);
+        data.collection_items(&keys)
+            .into_iter()
+            .flat_map(|x| x.attachments())
+            .filter(|x| x.is_stored_file())
+            .collect()
+    }
+
+    /// keys of every attachment which is kept offline
+    pub fn pinned_keys(&self, data: &Data) -> AHashSet<String> {
+        self.pins
+            .iter()
+            .flat_map(|x| Self::attachments(x, data))
+            .map(|x| x.key.clone())
+            .collect()
+    }
+
+    /// queue downloads for pinned attachments that are missing or outdated.
+    ///
+    /// a download first compares the local file with the server, so files which
+    /// have local changes are left alone.
+    pub fn sync(&self, data: &Data, transfers: &TransferManager) {
+        let mut queued = 0;
+        for item in self.pins.iter().flat_map(|x| Self::attachments(x, data)) {
+            let exists = super::attachment_file_path(item).is_some_and(|x| x.exists());
+            let synced = SyncState::load(&item.key);
+            if !needs_download(exists, item.data.md5.as_deref(), synced.as_ref()) {
+                continue;
+            }
+            let filename = item.data.filename.clone().unwrap_or_default();
+            transfers.enqueue(
+                Transfer::new(&item.key, TransferKind::Download, filename)
+                    .with_md5(item.data.md5.clone()),
+            );
+            queued += 1;
+        }
+        info!("pinned collections synced, {} downloads queued", queued);
+    }
+}
+
+/// the file is missing, or the api knows an md5 the file was not synced at. without
+/// an md5 from the api an existing file is only checked when it is opened.
+///
+/// a download that did not match its hash is moved aside, it is not retried until
+/// the api knows another md5.
+fn needs_download(exists: bool, md5: Option<&str>, synced: Option<&SyncState>) -> bool {
+    match (md5, synced) {
+        (md5, Some(synced)) if !synced.verified => md5.is_some_and(|x| x != synced.expected),
+        _ if !exists => true,
+        (Some(md5), Some(synced)) => synced.hash != md5,
+        (Some(_), None) => true,
+        (None, _) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn synced(hash: &str, verified: bool, expected: &str) -> SyncState {
+        SyncState {
+            hash: hash.to_string(),
+            mtime: 0,
+            verified,
+            expected: expected.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_needs_download() {
+        assert!(needs_download(false, Some("a"), None));
+        assert!(needs_download(false, None, None));
+        assert!(!needs_download(
+            true,
+            Some("a"),
+            Some(&synced("a", true, ""))
+        ));
+        assert!(needs_download(
+            true,
+            Some("b"),
+            Some(&synced("a", true, ""))
+        ));
+        assert!(needs_download(true, Some("a"), None));
+        assert!(!needs_download(true, None, None));
+
+        // the corrupt download was moved aside, it is not fetched again for the same md5
+        let corrupt = synced("c", false, "a");
+        assert!(!needs_download(false, Some("a"), Some(&corrupt)));
+        assert!(needs_download(false, Some("b"), Some(&corrupt)));
+        assert!(!needs_download(false, None, Some(&corrupt)));
+    }
+}
@@ -0,0 +1,39 @@
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use super::attachment_dir;
+
+const SYNC_STATE_FILE: &str = ".sync.json";
+
+/// what the local file of an attachment looked like the last time it matched the server,
+/// saved next to the file in the attachment's directory
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SyncState {
+    /// md5 of the file when it was last downloaded or uploaded
+    pub hash: String,
+    /// remote modify time in ms
+    pub mtime: i64,
+    /// false when the downloaded file did not match the remote hash
+    pub verified: bool,
+    /// the remote hash an unverified file was checked against
+    pub expected: String,
+}
+
+impl SyncState {
+    fn path(key: &str) -> PathBuf {
+        attachment_dir(key).join(SYNC_STATE_FILE)
+    }
+
+    pub fn load(key: &str) -> Option<Self> {
+        std::fs::read_to_string(Self::path(key))
+            .ok()
+            .and_then(|x| serde_json::from_str(&x).ok())
+    }
+
+    pub fn save(&self, key: &str) -> anyhow::Result<()> {
+        std::fs::write(Self::path(key), serde_json::to_string(self)?)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,74 @@
+use parking_lot::Mutex;
+use serde::Serialize;
+use tauri::async_runtime::JoinHandle;
+use tauri::{AppHandle, Emitter};
+use tokio::sync::mpsc;
+use tracing::{error, info};
+
+use crate::api::refresh;
+use crate::dal::zotero::stream::StreamEvent;
+use crate::dal::zotero::Zotero;
+
+/// emitted with a [`LibraryUpdate`] when a library changed on the server. changes
+/// of the user library are refreshed before.
+pub const LIBRARY_EVENT: &str = "library_updated";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LibraryUpdate {
+    /// `/users/<id>` or `/groups/<id>`
+    pub topic: String,
+    /// `None` after a reconnect, when the version is not known
+    pub version: Option<i64>,
+}
+
+/// the task handling the events of the current account's stream
+static TASK: Mutex<Option<JoinHandle<()>>> = Mutex::new(None);
+
+/// follow the libraries of `zotero` through the streaming api, in place of the
+/// stream of the previous client. nothing is followed for the local api.
+pub fn start(app: AppHandle, zotero: &Zotero) {
+    stop();
+    let Some(stream) = zotero.stream() else {
+        return;
+    };
+    let user_topic = format!("/users/{}", zotero.user_id());
+    let (tx, mut rx) = mpsc::channel(16);
+    tauri::async_runtime::spawn(stream.run(tx));
+    *TASK.lock() = Some(tauri::async_runtime::spawn(async move {
+        while let Some(event) = rx.recv().await {
+            let update = match event {
+                StreamEvent::TopicUpdated { topic, version } => LibraryUpdate {
+                    topic,
+                    version: Some(version),
+                },
+                StreamEvent::Reconnected => LibraryUpdate {
+                    topic: user_topic.clone(),
+                    version: None,
+                },
+                StreamEvent::TopicAdded { topic } | StreamEvent::TopicRemoved { topic } => {
+                    info!("stream topics changed: {}", topic);
+                    continue;
+                }
+            };
+            // only the user library is kept locally, the event still tells the
+            // frontend about group changes
+            if update.topic == user_topic {
+                if let Err(e) = refresh::reload(&app).await {
+                    error!("refresh after stream update failed: {:?}", e);
+                    continue;
+                }
+            }
+            if let Err(e) = app.emit(LIBRARY_EVENT, update) {
+                error!("emit library update failed: {:?}", e);
+            }
+        }
+    }));
+}
+
+/// stop following the stream, closing the connection
+pub fn stop() {
+    // dropping the receiver ends the stream task as well
+    if let Some(task) = TASK.lock().take() {
+        task.abort();
+    }
+}
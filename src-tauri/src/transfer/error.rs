@@ -0,0 +1,15 @@
+#[derive(Debug, thiserror::Error)]
+pub enum TransferError {
+    #[error("transfer failed: {0}")]
+    Failed(String),
+    #[error("transfer cancelled")]
+    Cancelled,
+    #[error("transfer paused")]
+    Paused,
+    #[error("transfer not found: {0}")]
+    NotFound(String),
+    #[error("downloaded file is corrupt, expected md5 {expected}, got {actual}")]
+    Integrity { expected: String, actual: String },
+    #[error("file of {0} failed the integrity check and will not be uploaded")]
+    Unverified(String),
+}
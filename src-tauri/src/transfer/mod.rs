@@ -0,0 +1,500 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use ahash::AHashMap;
+use error::TransferError;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use tauri::async_runtime::JoinHandle;
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::sync::{watch, Semaphore};
+use tracing::{error, info};
+
+use crate::dal::zotero::model::Feature;
+use crate::error::Error;
+use crate::storage;
+use crate::storage::sync_state::SyncState;
+use crate::AppState;
+use webdav::Properties;
+
+pub mod error;
+pub mod watcher;
+pub mod webdav;
+
+/// event emitted with a [`Transfer`] whenever its status or progress changes
+pub const TRANSFER_EVENT: &str = "transfer";
+
+const MAX_CONCURRENT_TRANSFERS: usize = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransferKind {
+    Download,
+    Upload,
+}
+
+impl TransferKind {
+    pub fn feature(self) -> Feature {
+        match self {
+            Self::Download => Feature::Download,
+            Self::Upload => Feature::Upload,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransferStatus {
+    Queued,
+    Running,
+    Paused,
+    Done,
+    Failed,
+    Cancelled,
+}
+
+impl TransferStatus {
+    pub fn is_finished(self) -> bool {
+        matches!(self, Self::Done | Self::Failed | Self::Cancelled)
+    }
+}
+
+/// a download or upload of one attachment, transfers are deduplicated by `key`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Transfer {
+    /// attachment key
+    pub key: String,
+    pub kind: TransferKind,
+    pub filename: String,
+    /// md5 reported by the zotero api, used when the webdav prop has no hash
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub md5: Option<String>,
+    pub status: TransferStatus,
+    #[serde(default)]
+    pub transferred: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Transfer {
+    pub fn new(key: impl Into<String>, kind: TransferKind, filename: impl Into<String>) -> Self {
+        Self {
+            key: key.into(),
+            kind,
+            filename: filename.into(),
+            md5: None,
+            status: TransferStatus::Queued,
+            transferred: 0,
+            total: None,
+            error: None,
+        }
+    }
+
+    pub fn with_md5(mut self, md5: Option<String>) -> Self {
+        self.md5 = md5;
+        self
+    }
+}
+
+/// decide how to bring a local file and the server in sync, `None` when they match.
+///
+/// `synced` is what the file looked like after the last transfer. without it the
+/// newer modify time wins.
+pub fn sync_direction(
+    local: &Properties,
+    remote: &Properties,
+    synced: Option<&SyncState>,
+) -> Option<TransferKind> {
+    if local.hash == remote.hash {
+        return None;
+    }
+    let kind = match synced {
+        // a file which failed the integrity check is never uploaded
+        Some(synced) if !synced.verified => TransferKind::Download,
+        Some(synced) if synced.hash == local.hash => TransferKind::Download,
+        Some(synced) if synced.hash == remote.hash => TransferKind::Upload,
+        _ if remote.mtime > local.mtime => TransferKind::Download,
+        _ => TransferKind::Upload,
+    };
+    Some(kind)
+}
+
+struct Entry {
+    state: watch::Sender<Transfer>,
+    handle: Option<JoinHandle<()>>,
+}
+
+struct Inner {
+    app: AppHandle,
+    semaphore: Arc<Semaphore>,
+    entries: Mutex<AHashMap<String, Entry>>,
+    /// unfinished transfers are saved here so they resume after restart
+    queue_path: PathBuf,
+}
+
+#[derive(Clone)]
+pub struct TransferManager {
+    inner: Arc<Inner>,
+}
+
+impl TransferManager {
+    /// create the manager with the transfers saved in `queue_path`, they start with
+    /// [`TransferManager::resume_saved`]
+    pub fn new(app: AppHandle, queue_path: PathBuf) -> Self {
+        let saved: Vec<Transfer> = std::fs::read_to_string(&queue_path)
+            .ok()
+            .and_then(|x| serde_json::from_str(&x).ok())
+            .unwrap_or_default();
+
+        let manager = Self {
+            inner: Arc::new(Inner {
+                app,
+                semaphore: Arc::new(Semaphore::new(MAX_CONCURRENT_TRANSFERS)),
+                entries: Mutex::new(AHashMap::new()),
+                queue_path,
+            }),
+        };
+
+        for mut transfer in saved {
+            info!("resume transfer: {:?}", transfer);
+            let paused = transfer.status == TransferStatus::Paused;
+            if !paused {
+                transfer.status = TransferStatus::Queued;
+            }
+            manager.inner.entries.lock().insert(
+                transfer.key.clone(),
+                Entry {
+                    state: watch::channel(transfer).0,
+                    handle: None,
+                },
+            );
+        }
+
+        manager
+    }
+
+    /// start the saved transfers which were not paused. they read the app state,
+    /// so this has to wait until it is managed.
+    pub fn resume_saved(&self) {
+        let keys: Vec<String> = self
+            .inner
+            .entries
+            .lock()
+            .iter()
+            .filter(|(_, x)| {
+                x.handle.is_none() && x.state.borrow().status == TransferStatus::Queued
+            })
+            .map(|(k, _)| k.clone())
+            .collect();
+        for key in keys {
+            self.spawn(&key);
+        }
+    }
+
+    pub fn list(&self) -> Vec<Transfer> {
+        self.inner
+            .entries
+            .lock()
+            .values()
+            .map(|x| x.state.borrow().clone())
+            .collect()
+    }
+
+    /// queue a transfer, if one for the same attachment is already queued or running it is reused
+    pub fn enqueue(&self, transfer: Transfer) {
+        let key = transfer.key.clone();
+        {
+            let mut entries = self.inner.entries.lock();
+            if let Some(entry) = entries.get(&key) {
+                let status = entry.state.borrow().status;
+                if matches!(status, TransferStatus::Queued | TransferStatus::Running) {
+                    return;
+                }
+            }
+            entries.insert(
+                key.clone(),
+                Entry {
+                    state: watch::channel(transfer.clone()).0,
+                    handle: None,
+                },
+            );
+        }
+        self.emit(&transfer);
+        self.persist();
+        self.spawn(&key);
+    }
+
+    /// wait until the transfer of `key` is finished
+    pub async fn wait(&self, key: &str) -> Result<(), TransferError> {
+        let mut rx = self
+            .inner
+            .entries
+            .lock()
+            .get(key)
+            .map(|x| x.state.subscribe())
+            .ok_or_else(|| TransferError::NotFound(key.to_string()))?;
+
+        loop {
+            {
+                let transfer = rx.borrow_and_update();
+                match transfer.status {
+                    TransferStatus::Done => return Ok(()),
+                    TransferStatus::Failed => {
+                        return Err(TransferError::Failed(
+                            transfer.error.clone().unwrap_or_default(),
+                        ))
+                    }
+                    TransferStatus::Cancelled => return Err(TransferError::Cancelled),
+                    TransferStatus::Paused => return Err(TransferError::Paused),
+                    TransferStatus::Queued | TransferStatus::Running => {}
+                }
+            }
+            if rx.changed().await.is_err() {
+                return Err(TransferError::Cancelled);
+            }
+        }
+    }
+
+    pub fn cancel(&self, key: &str) -> Result<(), TransferError> {
+        self.stop(key, TransferStatus::Cancelled)
+    }
+
+    /// cancel everything that is not finished, used when the account changes
+    pub fn cancel_all(&self) {
+        let keys: Vec<String> = self
+            .inner
+            .entries
+            .lock()
+            .iter()
+            .filter(|(_, x)| !x.state.borrow().status.is_finished())
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in keys {
+            self.cancel(&key).ok();
+        }
+    }
+
+    pub fn pause(&self, key: &str) -> Result<(), TransferError> {
+        self.stop(key, TransferStatus::Paused)
+    }
+
+    pub fn resume(&self, key: &str) -> Result<(), TransferError> {
+        {
+            let entries = self.inner.entries.lock();
+            let entry = entries
+                .get(key)
+                .ok_or_else(|| TransferError::NotFound(key.to_string()))?;
+            if entry.state.borrow().status != TransferStatus::Paused {
+                return Ok(());
+            }
+        }
+        self.update(key, |x| x.status = TransferStatus::Queued);
+        self.spawn(key);
+        Ok(())
+    }
+
+    fn stop(&self, key: &str, status: TransferStatus) -> Result<(), TransferError> {
+        {
+            let mut entries = self.inner.entries.lock();
+            let entry = entries
+                .get_mut(key)
+                .ok_or_else(|| TransferError::NotFound(key.to_string()))?;
+            if entry.state.borrow().status.is_finished() {
+                return Ok(());
+            }
+            if let Some(handle) = entry.handle.take() {
+                handle.abort();
+            }
+        }
+        info!("transfer {} stopped: {:?}", key, status);
+        self.update(key, |x| x.status = status);
+        if status == TransferStatus::Cancelled {
+            let key = key.to_string();
+            tauri::async_runtime::spawn(async move { webdav::discard_partial(&key).await });
+        }
+        Ok(())
+    }
+
+    fn spawn(&self, key: &str) {
+        let this = self.clone();
+        let task_key = key.to_string();
+        let handle = tauri::async_runtime::spawn(async move { this.run(&task_key).await });
+        if let Some(entry) = self.inner.entries.lock().get_mut(key) {
+            entry.handle = Some(handle);
+        }
+    }
+
+    async fn run(&self, key: &str) {
+        let Ok(_permit) = self.inner.semaphore.clone().acquire_owned().await else {
+            return;
+        };
+        let Some(transfer) = self.get(key) else {
+            return;
+        };
+        if transfer.status != TransferStatus::Queued {
+            return;
+        }
+        self.update(key, |x| x.status = TransferStatus::Running);
+
+        let (limits, client) = {
+            let state = self.inner.app.state::<Mutex<AppState>>();
+            let state = state.lock();
+            (
+                state.settings.extract_limits.clone(),
+                state
+                    .check(transfer.kind.feature())
+                    .map_err(Error::from)
+                    .and_then(|_| webdav::webdav_client(&state)),
+            )
+        };
+        let progress = |transferred, total| self.progress(key, transferred, total);
+        let result = match client {
+            Ok(client) => match transfer.kind {
+                TransferKind::Download => webdav::download(&client, &transfer, &limits, progress)
+                    .await
+                    .map(|_| ()),
+                TransferKind::Upload => {
+                    webdav::upload(&client, key, &transfer.filename, progress).await
+                }
+            },
+            Err(e) => Err(e),
+        };
+
+        match result {
+            Ok(()) => {
+                self.update(key, |x| x.status = TransferStatus::Done);
+                if transfer.kind == TransferKind::Download {
+                    crate::api::search::index_attachment(&self.inner.app, key);
+                    if let Err(e) = storage::cache::trim(&self.inner.app, Some(key)).await {
+                        error!("trim cache failed: {:?}", e);
+                    }
+                }
+            }
+            Err(e) => {
+                error!("transfer {} failed: {:?}", key, e);
+                self.update(key, |x| {
+                    x.status = TransferStatus::Failed;
+                    x.error = Some(e.to_string());
+                });
+            }
+        }
+    }
+
+    fn get(&self, key: &str) -> Option<Transfer> {
+        self.inner
+            .entries
+            .lock()
+            .get(key)
+            .map(|x| x.state.borrow().clone())
+    }
+
+    fn progress(&self, key: &str, transferred: u64, total: Option<u64>) {
+        let transfer = {
+            let entries = self.inner.entries.lock();
+            let Some(entry) = entries.get(key) else {
+                return;
+            };
+            entry.state.send_modify(|x| {
+                x.transferred = transferred;
+                x.total = total;
+            });
+            let transfer = entry.state.borrow().clone();
+            transfer
+        };
+        self.emit(&transfer);
+    }
+
+    /// change the status of a transfer, then notify the frontend and save the queue
+    fn update(&self, key: &str, f: impl FnOnce(&mut Transfer)) {
+        let transfer = {
+            let entries = self.inner.entries.lock();
+            let Some(entry) = entries.get(key) else {
+                return;
+            };
+            entry.state.send_modify(f);
+            let transfer = entry.state.borrow().clone();
+            transfer
+        };
+        self.emit(&transfer);
+        self.persist();
+    }
+
+    fn emit(&self, transfer: &Transfer) {
+        if let Err(e) = self.inner.app.emit(TRANSFER_EVENT, transfer) {
+            error!("emit transfer event failed: {:?}", e);
+        }
+    }
+
+    fn persist(&self) {
+        let unfinished: Vec<Transfer> = self
+            .list()
+            .into_iter()
+            .filter(|x| !x.status.is_finished())
+            .collect();
+        let result = serde_json::to_string(&unfinished)
+            .map_err(anyhow::Error::from)
+            .and_then(|x| Ok(std::fs::write(&self.inner.queue_path, x)?));
+        if let Err(e) = result {
+            error!("save transfer queue failed: {:?}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn prop(hash: &str, mtime: i64) -> Properties {
+        Properties {
+            mtime,
+            hash: hash.to_string(),
+        }
+    }
+
+    fn synced(hash: &str, verified: bool) -> SyncState {
+        SyncState {
+            hash: hash.to_string(),
+            mtime: 0,
+            verified,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_sync_direction() {
+        let local = prop("a", 10);
+        assert_eq!(sync_direction(&local, &prop("a", 20), None), None);
+
+        // local untouched since the last sync, remote changed
+        let s = synced("a", true);
+        assert_eq!(
+            sync_direction(&local, &prop("b", 5), Some(&s)),
+            Some(TransferKind::Download)
+        );
+
+        // local edited, remote untouched
+        let s = synced("b", true);
+        assert_eq!(
+            sync_direction(&local, &prop("b", 20), Some(&s)),
+            Some(TransferKind::Upload)
+        );
+
+        // corrupt download must not be uploaded even if it looks newer
+        let s = synced("a", false);
+        assert_eq!(
+            sync_direction(&local, &prop("b", 5), Some(&s)),
+            Some(TransferKind::Download)
+        );
+
+        assert_eq!(
+            sync_direction(&local, &prop("b", 20), None),
+            Some(TransferKind::Download)
+        );
+        assert_eq!(
+            sync_direction(&local, &prop("b", 5), None),
+            Some(TransferKind::Upload)
+        );
+    }
+}
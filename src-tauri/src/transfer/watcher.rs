@@ -0,0 +1,177 @@
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::time::{Duration, SystemTime};
+
+use ahash::AHashSet;
+use notify_debouncer_mini::new_debouncer;
+use notify_debouncer_mini::notify::RecursiveMode;
+use parking_lot::Mutex;
+use tauri::{AppHandle, Manager};
+use tracing::{debug, error, info, warn};
+
+use super::{Transfer, TransferKind};
+use crate::dal::zotero::model::Feature;
+use crate::storage::sync_state::SyncState;
+use crate::storage::{self, cache};
+use crate::AppState;
+
+/// file system events of one burst are reported together after this
+const DEBOUNCE: Duration = Duration::from_secs(5);
+/// a file has to stay untouched this long before it is uploaded, so a reader
+/// which is still saving does not get a half written file uploaded
+const QUIET_PERIOD: Duration = Duration::from_secs(10);
+
+/// bumped by every [`start`], older watcher threads stop at their next event
+static GENERATION: AtomicUsize = AtomicUsize::new(0);
+
+/// watch the attachment directories on a dedicated thread and upload files
+/// that were changed outside of the app. called again when the storage root changes.
+pub fn start(app: AppHandle) {
+    let generation = GENERATION.fetch_add(1, Ordering::SeqCst) + 1;
+    let root = storage::root();
+    std::thread::spawn(move || {
+        let (tx, rx) = mpsc::channel();
+        let mut debouncer = match new_debouncer(DEBOUNCE, tx) {
+            Ok(x) => x,
+            Err(e) => {
+                error!("create file watcher failed: {:?}", e);
+                return;
+            }
+        };
+        if let Err(e) = std::fs::create_dir_all(&root) {
+            error!("create {:?} failed: {:?}", root, e);
+            return;
+        }
+        if let Err(e) = debouncer.watcher().watch(&root, RecursiveMode::Recursive) {
+            error!("watch {:?} failed: {:?}", root, e);
+            return;
+        }
+        info!("watching {:?}", root);
+
+        for events in rx {
+            if GENERATION.load(Ordering::SeqCst) != generation {
+                info!("stop watching {:?}", root);
+                break;
+            }
+            match events {
+                Ok(events) => {
+                    let keys: AHashSet<String> = events
+                        .iter()
+                        .filter_map(|x| attachment_key(&root, &x.path))
+                        .collect();
+                    for key in keys {
+                        check(&app, &key);
+                    }
+                }
+                Err(e) => warn!("file watcher error: {:?}", e),
+            }
+        }
+    });
+}
+
+/// stop the running watcher, for accounts whose files are not uploaded
+pub fn stop() {
+    GENERATION.fetch_add(1, Ordering::SeqCst);
+}
+
+/// check every downloaded attachment, for when events may have been missed
+/// while the app was in the background
+pub fn scan(app: AppHandle) {
+    tauri::async_runtime::spawn_blocking(move || {
+        for entry in cache::entries() {
+            check(&app, &entry.key);
+        }
+    });
+}
+
+fn attachment_key(root: &Path, path: &Path) -> Option<String> {
+    let relative = path.strip_prefix(root).ok()?;
+    let key = relative.components().next()?.as_os_str().to_str()?;
+    (!key.starts_with('.')).then(|| key.to_string())
+}
+
+/// queue an upload when the attachment's file no longer matches what was last synced
+fn check(app: &AppHandle, key: &str) {
+    let (filename, transfers) = {
+        let state = app.state::<Mutex<AppState>>();
+        let state = state.lock();
+        if state.check(Feature::Upload).is_err() {
+            return;
+        }
+        let Some(item) = state.data.as_ref().and_then(|x| x.find_attachment(key)) else {
+            return;
+        };
+        if !item.is_stored_file() {
+            return;
+        }
+        (
+            item.data.filename.clone().unwrap_or_default(),
+            state.transfers.clone(),
+        )
+    };
+    if transfers
+        .list()
+        .iter()
+        .any(|x| x.key == key && !x.status.is_finished())
+    {
+        return;
+    }
+
+    // only files that were downloaded or uploaded before are tracked, unverified
+    // files would never be accepted for upload
+    let Some(synced) = SyncState::load(key).filter(|x| x.verified) else {
+        return;
+    };
+    let path = storage::file_path(key, &filename);
+    let Ok(modified) = std::fs::metadata(&path).and_then(|x| x.modified()) else {
+        return;
+    };
+    if chrono::DateTime::<chrono::Utc>::from(modified).timestamp_millis() == synced.mtime {
+        return;
+    }
+
+    let age = SystemTime::now()
+        .duration_since(modified)
+        .unwrap_or_default();
+    if age < QUIET_PERIOD {
+        debug!("{:?} changed recently, check again later", path);
+        recheck(app.clone(), key.to_string(), QUIET_PERIOD - age);
+        return;
+    }
+
+    if cache::has_local_changes(key, &filename) {
+        info!("{:?} changed, queue upload", path);
+        transfers.enqueue(Transfer::new(key, TransferKind::Upload, filename));
+    }
+}
+
+fn recheck(app: AppHandle, key: String, delay: Duration) {
+    tauri::async_runtime::spawn(async move {
+        tokio::time::sleep(delay).await;
+        tauri::async_runtime::spawn_blocking(move || check(&app, &key));
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::DOCUMENT_PATH;
+
+    #[test]
+    fn test_attachment_key() {
+        let dir = Path::new(DOCUMENT_PATH);
+        assert_eq!(
+            attachment_key(dir, &dir.join("ABCD1234").join("paper.pdf")),
+            Some("ABCD1234".to_string())
+        );
+        assert_eq!(
+            attachment_key(dir, &dir.join(".quarantine").join("a.zip")),
+            None
+        );
+        assert_eq!(
+            attachment_key(dir, Path::new("/tmp/ABCD1234/paper.pdf")),
+            None
+        );
+    }
+}
@@ -0,0 +1,475 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, UNIX_EPOCH};
+
+use anyhow::Context;
+use futures_util::StreamExt;
+use md5::{Digest, Md5};
+use reqwest::header::{
+    HeaderMap, HeaderName, CONTENT_LENGTH, ETAG, IF_RANGE, LAST_MODIFIED, RANGE,
+};
+use reqwest::{Body, StatusCode};
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::watch;
+use tokio_util::io::ReaderStream;
+use tracing::{debug, error, info, warn};
+
+use super::error::TransferError;
+use super::{sync_direction, Transfer, TransferKind};
+use crate::credentials;
+use crate::dal::webdav::error::WebDavError;
+use crate::dal::webdav::{client, WebDavAuth, WebDavClient};
+use crate::error::Error;
+use crate::storage;
+use crate::storage::extract::{self, ExtractLimits};
+use crate::storage::sync_state::SyncState;
+use crate::AppState;
+
+const MAX_RETRIES: u32 = 5;
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(1);
+const RETRY_MAX_DELAY: Duration = Duration::from_secs(30);
+
+/// client for the current account's webdav server, fails with
+/// [`WebDavError::NotConfigured`] until its settings and password are saved
+pub fn webdav_client(state: &AppState) -> Result<Arc<WebDavClient>, Error> {
+    let account = state.accounts.current().ok_or(WebDavError::NotConfigured)?;
+    let set = |x: &Option<String>| x.clone().filter(|x| !x.is_empty());
+    let (Some(url), Some(username)) = (set(&account.webdav.url), set(&account.webdav.username))
+    else {
+        return Err(WebDavError::NotConfigured.into());
+    };
+    let password = state
+        .credentials
+        .get(&credentials::webdav_password_name(account.user_id))?
+        .ok_or(WebDavError::NotConfigured)?;
+    Ok(client(url, Some(WebDavAuth { username, password }))?)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Properties {
+    pub mtime: i64,
+    pub hash: String,
+}
+
+impl Properties {
+    fn to_xml(&self) -> String {
+        format!(
+            "<properties version=\"1\"><mtime>{}</mtime><hash>{}</hash></properties>",
+            self.mtime, self.hash
+        )
+    }
+}
+
+pub async fn get_prop(client: &WebDavClient, key: &str) -> Result<Properties, Error> {
+    let prop = client
+        .get("/zotero/".to_string() + key + ".prop", HeaderMap::new())
+        .await?
+        .text()
+        .await?;
+    Ok(quick_xml::de::from_str(&prop).context("parse prop failed")?)
+}
+
+/// md5 and modify time (ms) of a local file, the file is hashed in chunks
+pub async fn local_prop(path: &Path) -> Result<Properties, Error> {
+    let path = path.to_path_buf();
+    tokio::task::spawn_blocking(move || -> Result<Properties, Error> {
+        let mut file = std::fs::File::open(&path).context("open file failed")?;
+        let meta = file.metadata()?;
+        let mtime = chrono::DateTime::<chrono::Utc>::from(meta.modified()?).timestamp_millis();
+        let mut h = Md5::new();
+        io::copy(&mut file, &mut h)?;
+        Ok(Properties {
+            mtime,
+            hash: hex::encode(h.finalize()),
+        })
+    })
+    .await?
+}
+
+/// download `{key}.zip` and extract it into the attachment's directory.
+///
+/// archives breaking `limits` are quarantined and never extracted. the extracted
+/// file is checked against the remote md5 and gets the remote modify time.
+pub async fn download(
+    client: &WebDavClient,
+    transfer: &Transfer,
+    limits: &ExtractLimits,
+    mut progress: impl FnMut(u64, Option<u64>),
+) -> Result<PathBuf, Error> {
+    let key = transfer.key.as_str();
+    let path = storage::attachment_dir(key);
+    let file_path = storage::file_path(key, &transfer.filename);
+
+    if file_path.exists() {
+        let remote = get_prop(client, key).await?;
+        let local = local_prop(&file_path).await?;
+        let synced = SyncState::load(key);
+        match sync_direction(&local, &remote, synced.as_ref()) {
+            None => {
+                info!("{} is up to date", key);
+                if synced.is_none() {
+                    SyncState {
+                        hash: local.hash,
+                        mtime: remote.mtime,
+                        verified: true,
+                        ..Default::default()
+                    }
+                    .save(key)?;
+                }
+                return Ok(file_path);
+            }
+            Some(TransferKind::Upload) => {
+                warn!("{} has local changes, skip download", key);
+                return Ok(file_path);
+            }
+            Some(TransferKind::Download) => {}
+        }
+    }
+
+    fs::create_dir_all(&path)
+        .await
+        .context("create dir failed")?;
+
+    let zip_path = fetch_zip(client, key, &mut progress).await?;
+
+    let limits = limits.clone();
+    tokio::task::spawn_blocking(move || -> Result<(), Error> {
+        match extract::extract(&zip_path, &path, &limits) {
+            Ok(_) => {
+                std::fs::remove_file(&zip_path)?;
+                Ok(())
+            }
+            Err(e) if e.is_violation() => {
+                extract::quarantine(&zip_path, &storage::quarantine_dir())?;
+                Err(e.into())
+            }
+            Err(e) => {
+                std::fs::remove_file(&zip_path).ok();
+                Err(e.into())
+            }
+        }
+    })
+    .await??;
+
+    let remote = match get_prop(client, key).await {
+        Ok(prop) => prop,
+        Err(e) => match &transfer.md5 {
+            Some(md5) => {
+                warn!("get prop of {} failed, use api md5: {:?}", key, e);
+                Properties {
+                    mtime: 0,
+                    hash: md5.clone(),
+                }
+            }
+            None => return Err(e),
+        },
+    };
+    verify(key, &file_path, &remote).await?;
+
+    info!("file_path: {:?}", file_path);
+    Ok(file_path)
+}
+
+/// compare the downloaded file with the remote hash and record the result in
+/// [`SyncState`]. a corrupt file is renamed so it is neither opened nor uploaded.
+async fn verify(key: &str, path: &Path, remote: &Properties) -> Result<(), Error> {
+    let actual = if path.exists() {
+        local_prop(path).await?.hash
+    } else {
+        String::new()
+    };
+
+    if actual != remote.hash {
+        error!(
+            "integrity check of {:?} failed, expected: {}, actual: {}",
+            path, remote.hash, actual
+        );
+        if path.exists() {
+            let mut corrupt = path.as_os_str().to_owned();
+            corrupt.push(".corrupt");
+            fs::rename(path, corrupt).await?;
+        }
+        SyncState {
+            hash: actual.clone(),
+            mtime: remote.mtime,
+            verified: false,
+            expected: remote.hash.clone(),
+        }
+        .save(key)?;
+        return Err(TransferError::Integrity {
+            expected: remote.hash.clone(),
+            actual,
+        }
+        .into());
+    }
+
+    if remote.mtime > 0 {
+        let mtime = UNIX_EPOCH + Duration::from_millis(remote.mtime as u64);
+        std::fs::File::options()
+            .write(true)
+            .open(path)?
+            .set_modified(mtime)?;
+    }
+    SyncState {
+        hash: actual,
+        mtime: remote.mtime,
+        verified: true,
+        ..Default::default()
+    }
+    .save(key)?;
+    Ok(())
+}
+
+/// validators of a partially downloaded zip, a resumed range is only accepted
+/// when the remote file still matches them
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct PartialMeta {
+    etag: Option<String>,
+    last_modified: Option<String>,
+}
+
+fn partial_path(key: &str) -> PathBuf {
+    storage::attachment_dir(key).join(format!("{}.zip.part", key))
+}
+
+fn partial_meta_path(key: &str) -> PathBuf {
+    storage::attachment_dir(key).join(format!("{}.zip.part.json", key))
+}
+
+/// remove the spooled data of an unfinished download
+pub async fn discard_partial(key: &str) {
+    for path in [partial_path(key), partial_meta_path(key)] {
+        if let Err(e) = fs::remove_file(&path).await {
+            if e.kind() != io::ErrorKind::NotFound {
+                warn!("remove partial file {:?} failed: {:?}", path, e);
+            }
+        }
+    }
+}
+
+/// spool `{key}.zip` into a partial file, resuming with `Range` requests and
+/// retrying transient failures. returns the path of the complete zip.
+async fn fetch_zip(
+    client: &WebDavClient,
+    key: &str,
+    progress: &mut impl FnMut(u64, Option<u64>),
+) -> Result<PathBuf, Error> {
+    let mut attempt = 0;
+    loop {
+        match fetch_zip_once(client, key, progress).await {
+            Ok(path) => return Ok(path),
+            Err(e) if attempt < MAX_RETRIES && is_transient(&e) => {
+                let delay = RETRY_BASE_DELAY * 2u32.pow(attempt);
+                let delay = delay.min(RETRY_MAX_DELAY);
+                warn!(
+                    "download {} failed, retry {} in {:?}: {:?}",
+                    key,
+                    attempt + 1,
+                    delay,
+                    e
+                );
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+async fn fetch_zip_once(
+    client: &WebDavClient,
+    key: &str,
+    progress: &mut impl FnMut(u64, Option<u64>),
+) -> Result<PathBuf, Error> {
+    let part_path = partial_path(key);
+    let meta_path = partial_meta_path(key);
+
+    let offset = fs::metadata(&part_path).await.map(|x| x.len()).unwrap_or(0);
+    let meta: PartialMeta = fs::read_to_string(&meta_path)
+        .await
+        .ok()
+        .and_then(|x| serde_json::from_str(&x).ok())
+        .unwrap_or_default();
+
+    let mut headers = HeaderMap::new();
+    let validator = meta.etag.as_ref().or(meta.last_modified.as_ref());
+    if let (true, Some(validator)) = (offset > 0, validator) {
+        debug!("resume download {} from {} bytes", key, offset);
+        headers.insert(RANGE, format!("bytes={}-", offset).parse().unwrap());
+        headers.insert(IF_RANGE, validator.parse().context("invalid validator")?);
+    }
+
+    let resp = match client
+        .get("/zotero/".to_string() + key + ".zip", headers)
+        .await
+    {
+        Err(WebDavError::Response(e)) if e.status() == Some(StatusCode::RANGE_NOT_SATISFIABLE) => {
+            // the remote file shrank, start over on the next attempt
+            discard_partial(key).await;
+            return Err(e.into());
+        }
+        resp => resp?,
+    };
+
+    let resumed = resp.status() == StatusCode::PARTIAL_CONTENT;
+    let mut file = if resumed {
+        fs::OpenOptions::new().append(true).open(&part_path).await?
+    } else {
+        let meta = PartialMeta {
+            etag: header_value(&resp, ETAG),
+            last_modified: header_value(&resp, LAST_MODIFIED),
+        };
+        fs::write(
+            &meta_path,
+            serde_json::to_string(&meta).context("encode meta failed")?,
+        )
+        .await?;
+        fs::File::create(&part_path).await?
+    };
+
+    let mut written = if resumed { offset } else { 0 };
+    let total = resp.content_length().map(|x| x + written);
+
+    let mut stream = resp.bytes_stream();
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk?;
+        file.write_all(&chunk).await?;
+        written += chunk.len() as u64;
+        progress(written, total);
+    }
+    file.flush().await?;
+
+    let zip_path = storage::attachment_dir(key).join(format!("{}.zip", key));
+    fs::rename(&part_path, &zip_path).await?;
+    fs::remove_file(&meta_path).await.ok();
+    Ok(zip_path)
+}
+
+fn header_value(resp: &reqwest::Response, name: HeaderName) -> Option<String> {
+    resp.headers()
+        .get(name)
+        .and_then(|x| x.to_str().ok())
+        .map(str::to_string)
+}
+
+/// network failures and server side errors are worth retrying, the data
+/// received so far is kept in the partial file
+fn is_transient(e: &Error) -> bool {
+    let e = match e {
+        Error::DownloadFile(e) => e,
+        Error::WebDav(WebDavError::Response(e)) => e,
+        Error::WebDav(WebDavError::Client(_)) => return true,
+        _ => return false,
+    };
+    match e.status() {
+        Some(status) => {
+            status.is_server_error()
+                || status == StatusCode::TOO_MANY_REQUESTS
+                || status == StatusCode::RANGE_NOT_SATISFIABLE
+        }
+        None => e.is_timeout() || e.is_connect() || e.is_body() || e.is_request(),
+    }
+}
+
+/// zip the local file and replace `{key}.zip` and `{key}.prop` on the server.
+///
+/// the zip is built in a temporary file next to the attachment and streamed to the server.
+pub async fn upload(
+    client: &WebDavClient,
+    key: &str,
+    filename: &str,
+    mut progress: impl FnMut(u64, Option<u64>),
+) -> Result<(), Error> {
+    if SyncState::load(key).is_some_and(|x| !x.verified) {
+        return Err(TransferError::Unverified(key.to_string()).into());
+    }
+
+    let path = storage::file_path(key, filename);
+    let prop = local_prop(&path).await?;
+    debug!("upload {:?}, prop: {:?}", path, prop);
+
+    let dir = storage::attachment_dir(key);
+    fs::create_dir_all(&dir)
+        .await
+        .context("create dir failed")?;
+    let zip_path = dir.join(format!("{}.upload.zip", key));
+
+    let result = async {
+        build_zip(&path, filename, &zip_path).await?;
+        put_file(client, key, &zip_path, &mut progress).await?;
+        client
+            .put(
+                "/zotero/".to_string() + key + ".prop",
+                prop.to_xml(),
+                HeaderMap::new(),
+            )
+            .await?;
+        SyncState {
+            hash: prop.hash.clone(),
+            mtime: prop.mtime,
+            verified: true,
+            ..Default::default()
+        }
+        .save(key)?;
+        Ok(())
+    }
+    .await;
+
+    fs::remove_file(&zip_path).await.ok();
+    result
+}
+
+async fn build_zip(path: &Path, filename: &str, zip_path: &Path) -> Result<(), Error> {
+    let path = path.to_path_buf();
+    let filename = filename.to_string();
+    let zip_path = zip_path.to_path_buf();
+    tokio::task::spawn_blocking(move || -> Result<(), Error> {
+        let mut source = std::fs::File::open(&path).context("open file failed")?;
+        let mut zip = zip::ZipWriter::new(std::fs::File::create(&zip_path)?);
+        let options = zip::write::SimpleFileOptions::default().large_file(true);
+
+        zip.start_file(filename, options)?;
+        io::copy(&mut source, &mut zip)?;
+        zip.finish()?;
+        Ok(())
+    })
+    .await?
+}
+
+async fn put_file(
+    client: &WebDavClient,
+    key: &str,
+    zip_path: &Path,
+    progress: &mut impl FnMut(u64, Option<u64>),
+) -> Result<(), Error> {
+    let file = fs::File::open(zip_path).await?;
+    let total = file.metadata().await?.len();
+
+    let (sent_tx, mut sent_rx) = watch::channel(0u64);
+    let mut sent = 0;
+    let stream = ReaderStream::new(file).inspect(move |chunk| {
+        if let Ok(chunk) = chunk {
+            sent += chunk.len() as u64;
+            sent_tx.send_replace(sent);
+        }
+    });
+
+    let mut headers = HeaderMap::new();
+    headers.insert(CONTENT_LENGTH, total.into());
+    let put = client.put(
+        format!("/zotero/{}.zip", key),
+        Body::wrap_stream(stream),
+        headers,
+    );
+    tokio::pin!(put);
+
+    loop {
+        tokio::select! {
+            result = &mut put => return Ok(result?),
+            Ok(()) = sent_rx.changed() => progress(*sent_rx.borrow_and_update(), Some(total)),
+        }
+    }
+}
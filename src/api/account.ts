@@ -0,0 +1,59 @@
+import { invoke } from "@tauri-apps/api/core"
+
+export interface Endpoint {
+    api_url: string
+    stream_url: string
+}
+
+export interface WebDavSettings {
+    url: string | null
+    username: string | null
+}
+
+export interface Account {
+    user_id: number
+    user_name: string
+    endpoint: Endpoint
+    webdav: WebDavSettings
+    storage_root: string | null
+    database: string | null
+}
+
+export interface AccountList {
+    accounts: Account[]
+    current: number | null
+}
+
+export const list_accounts = async (): Promise<AccountList> => {
+    return await invoke("list_accounts")
+}
+
+export const switch_account = async (user_id: number) => {
+    await invoke("switch_account", { user_id })
+}
+
+// `user_id` defaults to the current account
+export const logout = async (user_id: number | null, remove_files: boolean) => {
+    await invoke("logout", { user_id, remove_files })
+}
+
+export const update_account = async (
+    user_id: number,
+    endpoint: Endpoint,
+    webdav: WebDavSettings,
+    storage_root: string | null,
+) => {
+    await invoke("update_account", { user_id, endpoint, webdav, storage_root })
+}
+
+// log in to the zotero desktop app through its local api, `storage_dir`
+// defaults to ~/Zotero/storage
+export const connect_local = async (endpoint: Endpoint | null = null, storage_dir: string | null = null) => {
+    await invoke("connect_local", { endpoint, storage_dir })
+}
+
+// read the library from a zotero.sqlite copied from zotero desktop, `storage_dir`
+// defaults to the storage directory next to it
+export const connect_database = async (database: string, storage_dir: string | null = null) => {
+    await invoke("connect_database", { database, storage_dir })
+}
@@ -0,0 +1,9 @@
+import { invoke } from "@tauri-apps/api/core"
+
+export const get_base_directory = async (): Promise<string | null> => {
+    return await invoke("get_base_directory")
+}
+
+export const set_base_directory = async (path: string | null) => {
+    await invoke("set_base_directory", { path })
+}
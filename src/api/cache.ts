@@ -0,0 +1,22 @@
+import { invoke } from "@tauri-apps/api/core"
+
+export type CollectionUsage = {
+    collection_key: string
+    name: string
+    files: number
+    size: number
+}
+
+export type CacheUsage = {
+    total_size: number
+    quota: number
+    collections: CollectionUsage[]
+}
+
+export const get_cache_usage = async (): Promise<CacheUsage> => {
+    return await invoke("get_cache_usage")
+}
+
+export const trim_cache = async (): Promise<number> => {
+    return await invoke("trim_cache")
+}
@@ -0,0 +1,28 @@
+import { invoke } from "@tauri-apps/api/core"
+
+export type Feature = "notes" | "download" | "upload"
+
+export interface Disabled {
+    feature: Feature
+    reason: string
+}
+
+export interface GroupCapabilities {
+    // null for the access given to all groups
+    group_id: number | null
+    library: boolean
+    write: boolean
+}
+
+export interface Capabilities {
+    library: boolean
+    notes: boolean
+    files: boolean
+    write: boolean
+    groups: GroupCapabilities[]
+    disabled: Disabled[]
+}
+
+export const get_capabilities = async (): Promise<Capabilities> => {
+    return await invoke("get_capabilities")
+}
@@ -0,0 +1,13 @@
+import { invoke } from "@tauri-apps/api/core"
+
+export const is_credentials_locked = async (): Promise<boolean> => {
+    return await invoke("is_credentials_locked")
+}
+
+export const unlock_credentials = async (passphrase: string) => {
+    await invoke("unlock_credentials", { passphrase })
+}
+
+export const set_webdav_password = async (password: string | null) => {
+    await invoke("set_webdav_password", { password })
+}
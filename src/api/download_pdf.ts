@@ -1,5 +1,5 @@
-import { Channel, invoke } from "@tauri-apps/api/core"
+import { invoke } from "@tauri-apps/api/core"
 
-export const download_pdf = async (key: string, channel: Channel<number>): Promise<void> => {
-    return await invoke("download_pdf", { key, downloaded_size: channel })
+export const download_pdf = async (key: string, attachment_key?: string): Promise<void> => {
+    return await invoke("download_pdf", { key, attachment_key })
 }
@@ -0,0 +1,19 @@
+import { invoke } from "@tauri-apps/api/core"
+
+export type LinkMode = 'imported_file' | 'imported_url' | 'linked_file' | 'linked_url' | 'embedded_image' | 'unknown'
+
+export type LocalFileState = 'not_downloaded' | 'downloaded' | 'missing' | 'link'
+
+export type Attachment = {
+    key: string
+    title: string
+    content_type: string
+    filename?: string
+    link_mode?: LinkMode
+    remote_size?: number
+    local_state: LocalFileState
+}
+
+export const get_attachments = async (key: string): Promise<Attachment[]> => {
+    return await invoke("get_attachments", { key })
+}
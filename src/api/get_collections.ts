@@ -1,9 +1,15 @@
 import { invoke } from "@tauri-apps/api/core"
 
+/** a collection without its subcollections, they are listed after it */
 export type Collection = {
     name: string
     key: string
-    children: Collection[]
+    /** key of the parent collection, none at the top level */
+    father?: string
+    /** top level items directly in the collection */
+    item_count: number
+    /** items of the collection and its subcollections, each counted once */
+    total_count: number
 }
 
 export const get_collections = async (): Promise<[Collection[], number, number]> => {
@@ -1,10 +1,36 @@
 import { invoke } from "@tauri-apps/api/core"
+import type { LocalFileState } from "./get_attachments"
 
-export type SimpleItem = {
-    title: string
+export type SortKey = "title" | "creator" | "year" | "date_added" | "date_modified" | "item_type"
+
+export type SortOrder = "asc" | "desc"
+
+export type ItemRow = {
     key: string
+    title: string
+    creator_summary: string
+    year?: number
+    item_type: string
+    attachments: number
+    notes: number
+    local_state?: LocalFileState
+}
+
+export type ItemPage = {
+    rows: ItemRow[]
+    total: number
+    next_cursor?: string
+}
+
+export type ItemQuery = {
+    /** include the items of subcollections */
+    recursive?: boolean
+    sort?: SortKey
+    order?: SortOrder
+    cursor?: string
+    limit?: number
 }
 
-export const get_items_by_collection = async (collection_key: string): Promise<SimpleItem[]> => {
-    return await invoke("get_items_by_collection", { collection_key })
+export const get_items_by_collection = async (collection_key: string, query: ItemQuery = {}): Promise<ItemPage> => {
+    return await invoke("get_items_by_collection", { collection_key, ...query })
 }
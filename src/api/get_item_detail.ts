@@ -0,0 +1,57 @@
+import { invoke } from "@tauri-apps/api/core"
+import type { Attachment } from "./get_attachments"
+
+export type Field = {
+    field: string
+    label: string
+    value: string
+}
+
+export type Creator = {
+    creator_type: string
+    role: string
+    first_name: string
+    last_name: string
+}
+
+export type Tag = {
+    tag: string
+    automatic: boolean
+}
+
+export type CollectionPath = {
+    key: string
+    path: string[]
+}
+
+export type RelatedItem = {
+    key: string
+    title: string
+}
+
+export type Note = {
+    key: string
+    title: string
+    note: string
+    date_modified: string
+}
+
+export type ItemDetail = {
+    key: string
+    item_type: string
+    item_type_label: string
+    title: string
+    fields: Field[]
+    creators: Creator[]
+    tags: Tag[]
+    collections: CollectionPath[]
+    attachments: Attachment[]
+    notes: Note[]
+    related: RelatedItem[]
+    date_added: string
+    date_modified: string
+}
+
+export const get_item_detail = async (key: string, locale: string = navigator.language): Promise<ItemDetail> => {
+    return await invoke("get_item_detail", { key, locale })
+}
@@ -1,5 +1,11 @@
 import { invoke } from "@tauri-apps/api/core"
+import { listen, type UnlistenFn } from "@tauri-apps/api/event"
 
 export const is_login = async (): Promise<boolean> => {
     return await invoke("is_login")
 }
+
+// the stored api key was revoked, `reason` is the error message
+export const on_logout = async (handler: (reason: string) => void): Promise<UnlistenFn> => {
+    return await listen<string>("logout", (event) => handler(event.payload))
+}
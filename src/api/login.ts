@@ -1,5 +1,7 @@
 import { invoke } from "@tauri-apps/api/core"
+import type { Endpoint } from "./account"
 
-export const login = async (api_key: string) => {
-    await invoke("login", { api_key })
+// `endpoint` defaults to zotero.org
+export const login = async (api_key: string, endpoint: Endpoint | null = null) => {
+    await invoke("login", { api_key, endpoint })
 }
@@ -0,0 +1,16 @@
+import { invoke } from "@tauri-apps/api/core"
+import { listen, type UnlistenFn } from "@tauri-apps/api/event"
+
+export interface OAuthResult {
+    user_name: string | null
+    error: string | null
+}
+
+// opens the zotero authorize page, the result arrives through `on_oauth`
+export const start_oauth = async (): Promise<string> => {
+    return await invoke("start_oauth")
+}
+
+export const on_oauth = async (handler: (result: OAuthResult) => void): Promise<UnlistenFn> => {
+    return await listen<OAuthResult>("oauth", (event) => handler(event.payload))
+}
@@ -0,0 +1,24 @@
+import { invoke } from "@tauri-apps/api/core"
+
+export type PinStatus = {
+    collection_key: string
+    name: string
+    recursive: boolean
+    attachments: number
+    downloaded: number
+    pending: number
+    total_size: number
+    downloaded_size: number
+}
+
+export const pin_collection = async (collection_key: string, recursive: boolean) => {
+    await invoke("pin_collection", { collection_key, recursive })
+}
+
+export const unpin_collection = async (collection_key: string) => {
+    await invoke("unpin_collection", { collection_key })
+}
+
+export const get_pin_status = async (): Promise<PinStatus[]> => {
+    return await invoke("get_pin_status")
+}
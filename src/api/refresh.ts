@@ -1,5 +1,18 @@
 import { invoke } from "@tauri-apps/api/core"
+import { listen, type UnlistenFn } from "@tauri-apps/api/event"
+
+export interface LibraryUpdate {
+    // `/users/<id>` or `/groups/<id>`
+    topic: string
+    // null after the stream reconnected
+    version: number | null
+}
 
 export const refresh = async () => {
     await invoke("refresh")
 }
+
+// a library changed on the server, the user library is already refreshed
+export const on_library_updated = async (handler: (update: LibraryUpdate) => void): Promise<UnlistenFn> => {
+    return await listen<LibraryUpdate>("library_updated", (event) => handler(event.payload))
+}
@@ -0,0 +1,20 @@
+import { invoke } from "@tauri-apps/api/core"
+
+export interface SnippetPart {
+    text: string
+    highlight: boolean
+}
+
+export interface SearchHit {
+    key: string
+    // higher is better
+    score: number
+    title: SnippetPart[]
+    snippet: SnippetPart[]
+}
+
+// words match as prefixes, "quoted words" as a phrase, `author:`, `tag:`,
+// `title:` and `year:` (also `year:2010-2015`) search in one field
+export const search = async (query: string, limit: number | null = null): Promise<SearchHit[]> => {
+    return await invoke("search", { query, limit })
+}
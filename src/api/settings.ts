@@ -0,0 +1,21 @@
+import { invoke } from "@tauri-apps/api/core"
+
+export type ExtractLimits = {
+    max_entries: number
+    max_total_size: number
+    max_entry_size: number
+    max_compression_ratio: number
+}
+
+export type Settings = {
+    extract_limits: ExtractLimits
+    cache_quota: number
+}
+
+export const get_settings = async (): Promise<Settings> => {
+    return await invoke("get_settings")
+}
+
+export const set_settings = async (settings: Settings) => {
+    await invoke("set_settings", { settings })
+}
@@ -0,0 +1,40 @@
+import { invoke } from "@tauri-apps/api/core"
+import { listen, type UnlistenFn } from "@tauri-apps/api/event"
+
+export type TransferKind = 'download' | 'upload'
+
+export type TransferStatus = 'queued' | 'running' | 'paused' | 'done' | 'failed' | 'cancelled'
+
+export type Transfer = {
+    key: string
+    kind: TransferKind
+    filename: string
+    status: TransferStatus
+    transferred: number
+    total?: number
+    error?: string
+}
+
+export const list_transfers = async (): Promise<Transfer[]> => {
+    return await invoke("list_transfers")
+}
+
+export const queue_downloads = async (keys: string[]) => {
+    await invoke("queue_downloads", { keys })
+}
+
+export const cancel_transfer = async (key: string) => {
+    await invoke("cancel_transfer", { key })
+}
+
+export const pause_transfer = async (key: string) => {
+    await invoke("pause_transfer", { key })
+}
+
+export const resume_transfer = async (key: string) => {
+    await invoke("resume_transfer", { key })
+}
+
+export const on_transfer = async (handler: (transfer: Transfer) => void): Promise<UnlistenFn> => {
+    return await listen<Transfer>("transfer", (event) => handler(event.payload))
+}
@@ -1,7 +1,7 @@
 import { createApp } from "vue";
 import App from "./App.vue";
 import router from './router'
-import { is_login } from "./api/is_login";
+import { is_login, on_logout } from "./api/is_login";
 
 const init = async () => {
     const app = createApp(App)
@@ -10,6 +10,8 @@ const init = async () => {
     const initialRoute = isLoggedIn ? '/main' : '/'
     router.push(initialRoute)
 
+    await on_logout(() => router.push('/'))
+
     app.use(router).mount("#app")
 }
 